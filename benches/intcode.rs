@@ -0,0 +1,35 @@
+use aoc::intcode::{get_computer, parse_program};
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::fs;
+
+fn program(day: u32) -> Vec<i64> {
+    parse_program(&fs::read_to_string(format!("input/2019/day{}.txt", day)).unwrap())
+}
+
+fn run(program: &[i64], input: Vec<i64>, cached: bool) -> Vec<i64> {
+    let mut computer = get_computer(program, input);
+    if cached {
+        computer.enable_decode_cache();
+    }
+    computer.run_till_halt().unwrap()
+}
+
+// Day 9 part 2 runs a few hundred thousand instructions, day 5 a few dozen.
+// Each run starts with an empty cache, so day 5 shows what filling it costs a
+// short run, and day 9 how that pays off on a long one.
+fn decode_cache(c: &mut Criterion) {
+    for &(name, day, input) in &[("day9 part 2", 9, 2), ("day5 part 2", 5, 5)] {
+        let program = program(day);
+        let mut group = c.benchmark_group(name);
+        group.bench_function("decode every time", |b| {
+            b.iter(|| run(&program, vec![input], false))
+        });
+        group.bench_function("decode cache", |b| {
+            b.iter(|| run(&program, vec![input], true))
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, decode_cache);
+criterion_main!(benches);
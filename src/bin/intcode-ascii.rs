@@ -0,0 +1,27 @@
+use aoc::intcode::ascii::AsciiComputer;
+use aoc::intcode::{get_computer, parse_program};
+use std::io;
+use std::{env, fs, process};
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.len() != 1 {
+        eprintln!("usage: intcode-ascii <program>");
+        process::exit(1);
+    }
+    let source = fs::read_to_string(&args[0]).unwrap_or_else(|e| {
+        eprintln!("{}: {}", args[0], e);
+        process::exit(1);
+    });
+
+    let mut computer = AsciiComputer::new(get_computer(&parse_program(&source), vec![]));
+    let stdin = io::stdin();
+    match computer.interact(stdin.lock(), io::stdout()) {
+        Ok(Some(answer)) => println!("answer: {}", answer),
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
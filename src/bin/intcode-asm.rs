@@ -0,0 +1,26 @@
+use aoc::intcode::asm;
+use std::{env, fs, process};
+
+fn main() {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: intcode-asm <source>");
+            process::exit(1);
+        }
+    };
+    let source = fs::read_to_string(&path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(1);
+    });
+    match asm::assemble(&source) {
+        Ok(program) => {
+            let words: Vec<String> = program.iter().map(|w| w.to_string()).collect();
+            println!("{}", words.join(","));
+        }
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        }
+    }
+}
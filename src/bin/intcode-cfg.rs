@@ -0,0 +1,17 @@
+use aoc::intcode::{cfg, parse_program};
+use std::{env, fs, process};
+
+fn main() {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: intcode-cfg <program>");
+            process::exit(1);
+        }
+    };
+    let source = fs::read_to_string(&path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(1);
+    });
+    print!("{}", cfg::build(&parse_program(&source)).to_dot());
+}
@@ -0,0 +1,81 @@
+use aoc::intcode::debugger::{Command, Debugger};
+use aoc::intcode::{get_computer, parse_program};
+use std::io::{self, BufRead, Write};
+use std::{env, fs, process};
+
+// Instructions that can be undone with `back`
+const HISTORY: usize = 1_000_000;
+
+const USAGE: &str =
+    "usage: intcode-debug [--protect-code] [--allow <addr>]... <program> [input...]";
+
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    // Writes into code stop the program, except at the allowed addresses
+    let mut protect_code = false;
+    let mut allowed = Vec::new();
+    while let Some(flag) = args.first().filter(|a| a.starts_with("--")).cloned() {
+        args.remove(0);
+        match flag.as_str() {
+            "--protect-code" => protect_code = true,
+            "--allow" if !args.is_empty() => {
+                let addr = args.remove(0);
+                allowed.push(addr.parse::<usize>().unwrap_or_else(|_| {
+                    eprintln!("bad address: {}", addr);
+                    process::exit(1);
+                }));
+            }
+            _ => {
+                eprintln!("{}", USAGE);
+                process::exit(1);
+            }
+        }
+    }
+    if args.is_empty() {
+        eprintln!("{}", USAGE);
+        process::exit(1);
+    }
+    let source = fs::read_to_string(&args[0]).unwrap_or_else(|e| {
+        eprintln!("{}: {}", args[0], e);
+        process::exit(1);
+    });
+    let input = args[1..]
+        .iter()
+        .map(|a| a.parse::<i64>())
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap_or_else(|e| {
+            eprintln!("bad input value: {}", e);
+            process::exit(1);
+        });
+
+    let mut computer = get_computer(&parse_program(&source), input);
+    computer.enable_history(HISTORY);
+    if protect_code {
+        computer.protect_code();
+        for &addr in &allowed {
+            computer.allow_code_write(addr);
+        }
+    }
+    let mut debugger = Debugger::new(computer);
+    println!("{}", debugger.current());
+
+    let stdin = io::stdin();
+    let mut last = String::from("step");
+    loop {
+        print!("(icd) ");
+        io::stdout().flush().unwrap();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap() == 0 {
+            break;
+        }
+        // An empty line repeats the previous command
+        if !line.trim().is_empty() {
+            last = line.trim().to_string();
+        }
+        match Command::parse(&last) {
+            Ok(Command::Quit) => break,
+            Ok(command) => println!("{}", debugger.execute(command)),
+            Err(e) => println!("{}", e),
+        }
+    }
+}
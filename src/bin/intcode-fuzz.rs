@@ -0,0 +1,58 @@
+use aoc::intcode::fuzz::{check, generate};
+use aoc::intcode::{disasm, Signal};
+use std::collections::BTreeMap;
+use std::{env, process};
+
+const USAGE: &str = "usage: intcode-fuzz [--seed N] [--runs N]";
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut seed = 0;
+    let mut runs = 10_000;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = args.next().unwrap_or_else(|| fail(USAGE));
+        let value = value
+            .parse()
+            .unwrap_or_else(|_| fail(&format!("bad number `{}`", value)));
+        match arg.as_str() {
+            "--seed" => seed = value,
+            "--runs" => runs = value,
+            _ => fail(USAGE),
+        }
+    }
+
+    // How each program stopped, keyed by signal or error kind
+    let mut stops: BTreeMap<String, usize> = BTreeMap::new();
+    for seed in seed..seed + runs {
+        let case = generate(seed);
+        let stop = match check(&case) {
+            Ok(Some(outcome)) => match outcome.result {
+                Ok(Signal::Halt) => "halted".to_string(),
+                Ok(_) => "blocked on input".to_string(),
+                Err(e) => format!("{:?}", e)
+                    .split(' ')
+                    .next()
+                    .unwrap_or_default()
+                    .to_string(),
+            },
+            Ok(None) => "skipped".to_string(),
+            Err(failure) => {
+                println!("seed {}: {}", seed, failure);
+                println!("input {:?}", case.input);
+                print!("{}", disasm::listing(&case.program));
+                process::exit(1);
+            }
+        };
+        *stops.entry(stop).or_default() += 1;
+    }
+    println!("{} programs agreed on every backend", runs);
+    for (stop, count) in stops {
+        println!("{:>8}  {}", count, stop);
+    }
+}
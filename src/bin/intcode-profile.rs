@@ -0,0 +1,33 @@
+use aoc::intcode::{get_computer, parse_program};
+use std::{env, fs, process};
+
+// Hottest addresses listed in the report
+const TOP: usize = 20;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() {
+        eprintln!("usage: intcode-profile <program> [input...]");
+        process::exit(1);
+    }
+    let source = fs::read_to_string(&args[0]).unwrap_or_else(|e| {
+        eprintln!("{}: {}", args[0], e);
+        process::exit(1);
+    });
+    let input = args[1..]
+        .iter()
+        .map(|a| a.parse::<i64>())
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap_or_else(|e| {
+            eprintln!("bad input value: {}", e);
+            process::exit(1);
+        });
+
+    let mut computer = get_computer(&parse_program(&source), input);
+    computer.enable_profile();
+    match computer.run_till_halt() {
+        Ok(outputs) => println!("outputs: {:?}\n", outputs),
+        Err(e) => println!("stopped: {}\n", e),
+    }
+    print!("{}", computer.profile_report(TOP).unwrap());
+}
@@ -0,0 +1,78 @@
+use aoc::day2::{find_noun_verb, output_polynomial, TARGET};
+use aoc::intcode::parse_program;
+use std::ops::RangeInclusive;
+use std::{env, fs, process};
+
+const USAGE: &str = "usage: intcode-solve <program> [--target N] [--noun A..=B] [--verb A..=B]";
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
+
+// `A..=B` or `A..B`
+fn parse_range(s: &str) -> Option<RangeInclusive<i64>> {
+    let (start, end, inclusive) = match s.find("..=") {
+        Some(i) => (&s[..i], &s[i + 3..], true),
+        None => {
+            let i = s.find("..")?;
+            (&s[..i], &s[i + 2..], false)
+        }
+    };
+    let (start, end) = (start.parse().ok()?, end.parse::<i64>().ok()?);
+    match inclusive {
+        true => Some(start..=end),
+        false => Some(start..=end.checked_sub(1)?),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut path = None;
+    let mut target = TARGET;
+    let mut nouns = 0..=99;
+    let mut verbs = 0..=99;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| fail(USAGE));
+        match arg.as_str() {
+            "--target" => {
+                let value = value();
+                target = value
+                    .parse()
+                    .unwrap_or_else(|_| fail(&format!("bad target `{}`", value)));
+            }
+            "--noun" | "--verb" => {
+                let value = value();
+                let range =
+                    parse_range(value).unwrap_or_else(|| fail(&format!("bad range `{}`", value)));
+                match arg.as_str() {
+                    "--noun" => nouns = range,
+                    _ => verbs = range,
+                }
+            }
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+            _ => fail(USAGE),
+        }
+    }
+    let path = path.unwrap_or_else(|| fail(USAGE));
+    let source = fs::read_to_string(path).unwrap_or_else(|e| fail(&format!("{}: {}", path, e)));
+    let program = parse_program(&source);
+
+    match output_polynomial(&program) {
+        Some(poly) => println!("output = {}", poly),
+        None => println!("output is not a polynomial in noun and verb, searching every pair"),
+    }
+    match find_noun_verb(&program, target, nouns, verbs) {
+        Ok(Some((noun, verb))) => {
+            println!(
+                "noun = {}, verb = {}, answer {}",
+                noun,
+                verb,
+                100 * noun + verb
+            )
+        }
+        Ok(None) => println!("no noun and verb give {}", target),
+        Err(e) => fail(&e.to_string()),
+    }
+}
@@ -0,0 +1,60 @@
+use aoc::intcode::trace::{first_divergence, read_trace, write_trace};
+use aoc::intcode::{get_computer, parse_program};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::process;
+
+const USAGE: &str = "usage: intcode-trace record <program> <trace> [input...]
+       intcode-trace diff <trace> <trace>";
+
+fn fail(message: String) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
+
+fn record(args: &[String]) {
+    let source =
+        fs::read_to_string(&args[0]).unwrap_or_else(|e| fail(format!("{}: {}", args[0], e)));
+    let input = args[2..]
+        .iter()
+        .map(|a| a.parse::<i64>())
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap_or_else(|e| fail(format!("bad input value: {}", e)));
+
+    let mut computer = get_computer(&parse_program(&source), input);
+    computer.enable_trace();
+    let result = computer.run_till_halt();
+    let trace = computer.take_trace();
+
+    let out = File::create(&args[1]).unwrap_or_else(|e| fail(format!("{}: {}", args[1], e)));
+    write_trace(&trace, BufWriter::new(out))
+        .unwrap_or_else(|e| fail(format!("{}: {}", args[1], e)));
+    match result {
+        Ok(outputs) => println!("{} steps, outputs {:?}", trace.len(), outputs),
+        Err(e) => fail(format!("{} steps, then {}", trace.len(), e)),
+    }
+}
+
+fn diff(args: &[String]) {
+    let load = |path: &String| {
+        let file = File::open(path).unwrap_or_else(|e| fail(format!("{}: {}", path, e)));
+        read_trace(BufReader::new(file)).unwrap_or_else(|e| fail(format!("{}: {}", path, e)))
+    };
+    let (left, right) = (load(&args[0]), load(&args[1]));
+    match first_divergence(&left, &right) {
+        Some(divergence) => {
+            println!("{}", divergence);
+            process::exit(1);
+        }
+        None => println!("traces are identical ({} steps)", left.len()),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("record") if args.len() >= 3 => record(&args[1..]),
+        Some("diff") if args.len() == 3 => diff(&args[1..]),
+        _ => fail(USAGE.to_string()),
+    }
+}
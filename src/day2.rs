@@ -1,64 +1,67 @@
-#[derive(Debug, PartialEq, Eq)]
-pub enum Instruction {
-    ADD,
-    MULT,
-}
+use crate::intcode::compile::compile;
+use crate::intcode::symbolic::{solve, Poly, SymbolicComputer};
+use crate::intcode::{get_computer, IntcodeError};
+use std::ops::RangeInclusive;
 
-impl Instruction {
-    fn new(from: usize) -> Option<Instruction> {
-        match from {
-            1 => Some(Instruction::ADD),
-            2 => Some(Instruction::MULT),
-            _ => None,
-        }
-    }
+pub const TARGET: i64 = 19690720;
+
+fn exec_instructions(ins: &[i64], noun: i64, verb: i64) -> Result<i64, IntcodeError> {
+    let mut computer = get_computer(ins, vec![]);
+    computer.store_value_at_pos(1, noun);
+    computer.store_value_at_pos(2, verb);
+    computer.run_till_halt()?;
+    Ok(computer.get_value_at_pos(0))
 }
 
-fn exec_instructions(mut ins: Vec<usize>) -> Option<usize> {
-    let mut i: usize = 0;
+// The program's output as a polynomial in the noun and verb, as long as it
+// only adds and multiplies them
+pub fn output_polynomial(ins: &[i64]) -> Option<Poly> {
+    let mut computer = SymbolicComputer::new(ins);
+    computer.symbolize(1, "noun").symbolize(2, "verb");
+    computer.run().ok()?;
+    computer.value_at(0).to_poly()
+}
 
-    while let Some(instruction) = Instruction::new(ins[i]) {
-        let out_addr = ins[i + 3];
-        ins[out_addr] = match instruction {
-            Instruction::ADD => ins[ins[i + 1]] + ins[ins[i + 2]],
-            Instruction::MULT => ins[ins[i + 1]] * ins[ins[i + 2]],
-        };
-        i += 4;
+// The first noun and verb, searching nouns in the outer loop, for which the
+// program outputs `target`. Solved from the output polynomial when there is
+// one, otherwise every pair is run.
+pub fn find_noun_verb(
+    ins: &[i64],
+    target: i64,
+    nouns: RangeInclusive<i64>,
+    verbs: RangeInclusive<i64>,
+) -> Result<Option<(i64, i64)>, IntcodeError> {
+    if let Some(poly) = output_polynomial(ins) {
+        return Ok(solve(&poly, target, ("noun", nouns), ("verb", verbs)));
     }
-
-    ins.get(0).copied()
+    // Compiled once, then patched and run for every noun and verb
+    let program = compile(ins);
+    for noun in nouns {
+        for verb in verbs.clone() {
+            let mut machine = program.machine(vec![]);
+            machine.store_value_at_pos(1, noun);
+            machine.store_value_at_pos(2, verb);
+            machine.run_till_halt()?;
+            if machine.get_value_at_pos(0) == target {
+                return Ok(Some((noun, verb)));
+            }
+        }
+    }
+    Ok(None)
 }
 
 #[aoc_generator(day2)]
-pub fn parse_program(input: &str) -> Vec<usize> {
-    input
-        .split(",")
-        .map(|x| x.parse::<usize>().unwrap())
-        .collect()
+pub fn parse_program(input: &str) -> Vec<i64> {
+    crate::intcode::parse_program(input)
 }
 
 #[aoc(day2, part1)]
-pub fn solve_p1(instructions: &Vec<usize>) -> usize {
-    let mut instructions = instructions.clone();
-    instructions[1] = 12;
-    instructions[2] = 2;
-    exec_instructions(instructions).ok_or(0).unwrap()
+pub fn solve_p1(instructions: &[i64]) -> Result<i64, IntcodeError> {
+    exec_instructions(instructions, 12, 2)
 }
 
 #[aoc(day2, part2)]
-pub fn solve_p2(instructions: &Vec<usize>) -> usize {
-    let mut solution = 0;
-    'outer: for noun in 1..99 {
-        for verb in 1..99 {
-            let mut instructions = instructions.clone();
-            instructions[1] = noun;
-            instructions[2] = verb;
-            let output = exec_instructions(instructions).ok_or(0).unwrap();
-            if output == 19690720 {
-                solution = 100 * noun + verb;
-                break 'outer;
-            }
-        }
-    }
-    solution
+pub fn solve_p2(instructions: &[i64]) -> Result<i64, IntcodeError> {
+    let found = find_noun_verb(instructions, TARGET, 0..=99, 0..=99)?;
+    Ok(found.map_or(0, |(noun, verb)| 100 * noun + verb))
 }
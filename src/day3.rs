@@ -81,7 +81,7 @@ fn find_intersection(p0: &Point, p1: &Point, p2: &Point, p3: &Point) -> Option<P
     let s = (-s1_y * (p0_x - p2_x) + s1_x * (p0_y - p2_y)) / det;
     let t = (s2_x * (p0_y - p2_y) - s2_y * (p0_x - p2_x)) / det;
 
-    if s >= 0.0 && s <= 1.0 && t >= 0.0 && t <= 1.0 {
+    if (0.0..=1.0).contains(&s) && (0.0..=1.0).contains(&t) {
         let ix = (p0_x + (t * s1_x)) as i64;
         let iy = (p0_y + (t * s1_y)) as i64;
         Some((ix, iy))
@@ -112,11 +112,11 @@ fn find_intersections(path_1: Vec<Point>, path_2: Vec<Point>) -> Vec<(u64, Point
 }
 
 fn manhattan_distance(p: &Point, q: &Point) -> u64 {
-    (p.0 - q.0).abs() as u64 + (p.1 - q.1).abs() as u64
+    (p.0 - q.0).unsigned_abs() + (p.1 - q.1).unsigned_abs()
 }
 
 #[aoc(day3, part1)]
-pub fn solve_p1(input: &Vec<Vec<Instruction>>) -> u64 {
+pub fn solve_p1(input: &[Vec<Instruction>]) -> u64 {
     let wire_1 = gen_path(&input[0]);
     let wire_2 = gen_path(&input[1]);
 
@@ -130,7 +130,7 @@ pub fn solve_p1(input: &Vec<Vec<Instruction>>) -> u64 {
 }
 
 #[aoc(day3, part2)]
-pub fn solve_p2(input: &Vec<Vec<Instruction>>) -> u64 {
+pub fn solve_p2(input: &[Vec<Instruction>]) -> u64 {
     let wire_1 = gen_path(&input[0]);
     let wire_2 = gen_path(&input[1]);
 
@@ -21,7 +21,7 @@ fn always_increasing(s: &str) -> bool {
             last_max = x;
         }
     }
-    return true;
+    true
 }
 
 fn has_tuplets(s: &str) -> bool {
@@ -33,7 +33,7 @@ fn has_tuplets(s: &str) -> bool {
             last_seen = c;
         }
     }
-    return false;
+    false
 }
 
 fn valid_password(s: &str) -> bool {
@@ -20,7 +20,7 @@ fn solve_p1(orbits: &HashMap<String, Vec<String>>) -> u32 {
     queue.push_back(("COM", 1));
     let mut count: u32 = 0;
 
-    while queue.len() > 0 {
+    while !queue.is_empty() {
         let (parent, cur_count) = queue.pop_back().unwrap();
         if let Some(childs) = orbits.get(parent) {
             for child in childs {
@@ -1,3 +1,37 @@
+use crate::intcode::compile::{compile, CompiledMachine, CompiledProgram};
+use crate::intcode::{IntcodeError, Signal};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::thread;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum AmplifierError {
+    Machine(IntcodeError),
+    // The last amplifier finished without sending a signal
+    NoSignal,
+    // Every amplifier is waiting on input and none of them has any coming
+    Stalled,
+}
+
+impl fmt::Display for AmplifierError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AmplifierError::Machine(error) => write!(f, "{}", error),
+            AmplifierError::NoSignal => write!(f, "the amplifiers produced no signal"),
+            AmplifierError::Stalled => write!(f, "every amplifier is waiting for input"),
+        }
+    }
+}
+
+impl Error for AmplifierError {}
+
+impl From<IntcodeError> for AmplifierError {
+    fn from(error: IntcodeError) -> Self {
+        AmplifierError::Machine(error)
+    }
+}
+
 // https://rosettacode.org/wiki/Permutations#Iterative
 pub fn permutations(start: usize, end: usize) -> Permutations {
     Permutations {
@@ -39,272 +73,125 @@ impl Iterator for Permutations {
     }
 }
 
-enum Parameter {
-    Position(i64),
-    Immediate(i64),
-}
-
-#[derive(PartialEq)]
-enum Signal {
-    NeedsInput,
-    ProducedOutput,
-    Halt,
-    None,
-}
-
-enum Instruction {
-    Add((Parameter, Parameter, Parameter)),
-    Mul((Parameter, Parameter, Parameter)),
-    Input(Parameter),
-    Output(Parameter),
-    JumpIfTrue((Parameter, Parameter)),
-    JumpIfFalse((Parameter, Parameter)),
-    LessThan((Parameter, Parameter, Parameter)),
-    Equals((Parameter, Parameter, Parameter)),
-    Halt,
-}
-
-struct IntCodeComputer {
-    memory: Vec<i64>,
-    input: Vec<i64>,
-    output: Option<i64>,
-    instruction_pointer: usize,
+fn get_amplifiers<'p>(program: &'p CompiledProgram, phase: &[usize]) -> Vec<CompiledMachine<'p>> {
+    phase
+        .iter()
+        .map(|&p| program.machine(vec![p as i64]))
+        .collect()
 }
 
-impl IntCodeComputer {
-    fn new(mem: Vec<i64>, input: Vec<i64>) -> Self {
-        IntCodeComputer {
-            memory: mem,
-            input: input,
-            output: None,
-            instruction_pointer: 0,
-        }
-    }
-
-    fn get_word(&mut self) -> i64 {
-        let word = self.memory[self.instruction_pointer];
-        self.instruction_pointer += 1;
-        word
-    }
-
-    fn _get_parameter(&mut self, mode: i64) -> Parameter {
-        let word = self.get_word();
-        match mode {
-            0 => Parameter::Position(word),
-            1 => Parameter::Immediate(word),
-            _ => panic!("unknown parameter mode"),
-        }
-    }
-
-    fn get_param_1(&mut self, op_code: i64) -> Parameter {
-        self._get_parameter(op_code % 10)
-    }
-
-    fn get_param_2(&mut self, op_code: i64) -> (Parameter, Parameter) {
-        let p1 = self.get_param_1(op_code);
-        let p2 = self._get_parameter((op_code / 10) % 10);
-        (p1, p2)
-    }
-
-    fn get_param_3(&mut self, op_code: i64) -> (Parameter, Parameter, Parameter) {
-        let (p1, p2) = self.get_param_2(op_code);
-        let p3 = self._get_parameter((op_code / 100) % 10);
-        (p1, p2, p3)
-    }
-
-    fn unwrap(&self, param: Parameter) -> i64 {
-        match param {
-            Parameter::Immediate(val) => val,
-            Parameter::Position(pos) => self.memory[pos as usize],
-        }
-    }
-
-    fn store_val(&mut self, param: Parameter, val: i64) {
-        match param {
-            Parameter::Position(out) => {
-                self.memory[out as usize] = val;
-            }
-            _ => panic!("can not store to parameter in immediate mode"),
-        }
-    }
-
-    fn emit_output(&mut self, param: Parameter) {
-        self.output = Some(self.unwrap(param));
-    }
-
-    fn jump(&mut self, param: Parameter) {
-        self.instruction_pointer = self.unwrap(param) as usize;
-    }
-
-    fn feed_input(&mut self, inp: i64) {
-        self.input.push(inp);
+fn get_signal(program: &CompiledProgram, phase: &[usize]) -> Result<i64, AmplifierError> {
+    let mut signal = VecDeque::from(vec![0]);
+    for amplifier in get_amplifiers(program, phase).iter_mut() {
+        let mut out = VecDeque::new();
+        amplifier.run_with_io(&mut signal, &mut out)?;
+        signal = out;
     }
+    signal.front().copied().ok_or(AmplifierError::NoSignal)
+}
 
-    fn get_instruction(&mut self) -> Instruction {
-        let mut op_code = self.get_word();
-        let inst = op_code % 100;
-        op_code /= 100;
-
-        match inst {
-            1 => Instruction::Add(self.get_param_3(op_code)),
-            2 => Instruction::Mul(self.get_param_3(op_code)),
-            3 => Instruction::Input(self.get_param_1(op_code)),
-            4 => Instruction::Output(self.get_param_1(op_code)),
-            5 => Instruction::JumpIfTrue(self.get_param_2(op_code)),
-            6 => Instruction::JumpIfFalse(self.get_param_2(op_code)),
-            7 => Instruction::LessThan(self.get_param_3(op_code)),
-            8 => Instruction::Equals(self.get_param_3(op_code)),
-            99 => Instruction::Halt,
-            _ => panic!("unknown instruction"),
+// Each amplifier drains the signals queued by the previous one, so a round
+// ends with every amplifier blocked on input or, in the last round, halted
+fn get_signal_with_feedback(
+    program: &CompiledProgram,
+    phase: &[usize],
+) -> Result<i64, AmplifierError> {
+    let mut amplifiers = get_amplifiers(program, phase);
+    let mut signal = VecDeque::from(vec![0]);
+    loop {
+        let mut halted = false;
+        for amplifier in amplifiers.iter_mut() {
+            let mut out = VecDeque::new();
+            halted = amplifier.run_with_io(&mut signal, &mut out)? == Signal::Halt;
+            signal = out;
         }
-    }
-
-    fn get_output(&mut self) -> i64 {
-        let ret = self.output.unwrap();
-        self.output = None;
-        ret
-    }
-
-    fn tick(&mut self) -> Signal {
-        let inst = self.get_instruction();
-        match inst {
-            Instruction::Add((p_1, p_2, p_3)) => {
-                let op1 = self.unwrap(p_1);
-                let op2 = self.unwrap(p_2);
-                self.store_val(p_3, op1 + op2);
-                return Signal::None;
-            }
-            Instruction::Mul((p_1, p_2, p_3)) => {
-                let op1 = self.unwrap(p_1);
-                let op2 = self.unwrap(p_2);
-                self.store_val(p_3, op1 * op2);
-                return Signal::None;
-            }
-            Instruction::Input(p_) => {
-                if self.input.is_empty() {
-                    return Signal::NeedsInput;
-                }
-                let inp = self.input.remove(0);
-                self.store_val(p_, inp);
-                return Signal::None;
-            }
-            Instruction::Output(p_) => {
-                self.emit_output(p_);
-                return Signal::ProducedOutput;
-            }
-            Instruction::JumpIfTrue((p_1, p_2)) => {
-                if self.unwrap(p_1) != 0 {
-                    self.jump(p_2);
-                }
-                return Signal::None;
-            }
-            Instruction::JumpIfFalse((p_1, p_2)) => {
-                if self.unwrap(p_1) == 0 {
-                    self.jump(p_2);
-                }
-                return Signal::None;
-            }
-            Instruction::LessThan((p_1, p_2, p_3)) => {
-                let op1 = self.unwrap(p_1);
-                let op2 = self.unwrap(p_2);
-                self.store_val(p_3, if op1 < op2 { 1 } else { 0 });
-                return Signal::None;
-            }
-            Instruction::Equals((p_1, p_2, p_3)) => {
-                let op1 = self.unwrap(p_1);
-                let op2 = self.unwrap(p_2);
-                self.store_val(p_3, if op1 == op2 { 1 } else { 0 });
-                return Signal::None;
-            }
-            Instruction::Halt => return Signal::Halt,
+        match (halted, signal.is_empty()) {
+            (true, _) => return signal.back().copied().ok_or(AmplifierError::NoSignal),
+            // Nothing left to feed the first amplifier with
+            (false, true) => return Err(AmplifierError::Stalled),
+            (false, false) => {}
         }
     }
-
-    fn run_till_signal(&mut self, signal: Signal) {
-        while self.tick() != signal {}
-    }
-
-    fn run(&mut self) -> Signal {
-        let mut signal = self.tick();
-        while signal == Signal::None {
-            signal = self.tick();
-        }
-        signal
-    }
-}
-
-fn get_signal(instructions: &Vec<i64>, phase: Vec<usize>) -> i64 {
-    let mut amplifiers = vec![
-        IntCodeComputer::new(instructions.clone(), vec![phase[0] as i64]),
-        IntCodeComputer::new(instructions.clone(), vec![phase[1] as i64]),
-        IntCodeComputer::new(instructions.clone(), vec![phase[2] as i64]),
-        IntCodeComputer::new(instructions.clone(), vec![phase[3] as i64]),
-        IntCodeComputer::new(instructions.clone(), vec![phase[4] as i64]),
-    ];
-
-    let mut out = 0;
-    for i in 0..5 {
-        amplifiers[i].feed_input(out);
-        amplifiers[i].run_till_signal(Signal::ProducedOutput);
-        out = amplifiers[i].get_output();
-    }
-
-    out
 }
 
-fn get_signal_with_feedback(instructions: &Vec<i64>, phase: Vec<usize>) -> i64 {
-    let mut amplifiers = vec![
-        IntCodeComputer::new(instructions.clone(), vec![phase[0] as i64, 0]),
-        IntCodeComputer::new(instructions.clone(), vec![phase[1] as i64]),
-        IntCodeComputer::new(instructions.clone(), vec![phase[2] as i64]),
-        IntCodeComputer::new(instructions.clone(), vec![phase[3] as i64]),
-        IntCodeComputer::new(instructions.clone(), vec![phase[4] as i64]),
-    ];
-
-    let mut sig: Option<i64> = None;
-    for i in (0..5).cycle() {
-        if let Some(s) = sig {
-            amplifiers[i].feed_input(s);
-        }
-
-        match amplifiers[i].run() {
-            Signal::ProducedOutput => {
-                sig = Some(amplifiers[i].get_output());
-            }
-            Signal::Halt => {
-                if i == 4 {
-                    break;
-                }
-            }
-            _ => {}
+// Splits the phase permutations between one worker per core. Each
+// permutation runs its amplifiers on the worker's own thread: they only
+// exchange a handful of values, so a thread per amplifier (see
+// `intcode::pipeline`) costs far more in hand-offs than it saves. The
+// program is compiled once and shared by every amplifier.
+fn max_signal(
+    instructions: &[i64],
+    phases: Permutations,
+    signal: fn(&CompiledProgram, &[usize]) -> Result<i64, AmplifierError>,
+) -> Result<i64, AmplifierError> {
+    let program = &compile(instructions);
+    let phases: Vec<Vec<usize>> = phases.collect();
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = phases.len().div_ceil(workers);
+    thread::scope(|scope| {
+        let handles: Vec<_> = phases
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    let mut mx = i64::MIN;
+                    for phase in chunk {
+                        mx = mx.max(signal(program, phase)?);
+                    }
+                    Ok::<_, AmplifierError>(mx)
+                })
+            })
+            .collect();
+        let mut mx = i64::MIN;
+        for handle in handles {
+            mx = mx.max(handle.join().unwrap()?);
         }
-    }
-
-    sig.unwrap()
+        Ok(mx)
+    })
 }
 
 #[aoc_generator(day7)]
 fn parse_input(input: &str) -> Vec<i64> {
-    input
-        .split(",")
-        .filter_map(|x| x.parse::<i64>().ok())
-        .collect()
+    crate::intcode::parse_program(input)
 }
 
 #[aoc(day7, part1)]
-fn solve_p1(instructions: &Vec<i64>) -> Option<i64> {
-    let mut mx = std::i64::MIN;
-    for perm in permutations(0, 4) {
-        mx = ::std::cmp::max(mx, get_signal(&instructions, perm));
-    }
-    Some(mx)
+fn solve_p1(instructions: &[i64]) -> Result<i64, AmplifierError> {
+    max_signal(instructions, permutations(0, 4), get_signal)
 }
 
 #[aoc(day7, part2)]
-fn solve_p2(instructions: &Vec<i64>) -> Option<i64> {
-    let mut mx = std::i64::MIN;
-    for perm in permutations(5, 9) {
-        mx = ::std::cmp::max(mx, get_signal_with_feedback(&instructions, perm));
+fn solve_p2(instructions: &[i64]) -> Result<i64, AmplifierError> {
+    max_signal(instructions, permutations(5, 9), get_signal_with_feedback)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::parse_program;
+
+    fn signal_with(
+        program: &str,
+        signal: fn(&CompiledProgram, &[usize]) -> Result<i64, AmplifierError>,
+    ) -> Result<i64, AmplifierError> {
+        signal(&compile(&parse_program(program)), &[0, 1, 2, 3, 4])
+    }
+
+    #[test]
+    fn amplifiers_that_never_signal() {
+        // Reads its phase and signal, then halts without output
+        assert_eq!(
+            signal_with("3,0,3,0,99", get_signal),
+            Err(AmplifierError::NoSignal)
+        );
+        // Halts straight after reading its phase
+        assert_eq!(
+            signal_with("3,0,99", get_signal_with_feedback),
+            Err(AmplifierError::NoSignal)
+        );
+        // Reads its phase and signal, then waits for more that never comes
+        assert_eq!(
+            signal_with("3,0,3,0,3,0,99", get_signal_with_feedback),
+            Err(AmplifierError::Stalled)
+        );
     }
-    Some(mx)
 }
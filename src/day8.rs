@@ -50,12 +50,12 @@ impl Display for Layer {
         for row in self.rows() {
             for c in row {
                 match c {
-                    '0' => write!(f, "{}", ' ')?,
-                    '1' => write!(f, "{}", '*')?,
+                    '0' => write!(f, " ")?,
+                    '1' => write!(f, "*")?,
                     _ => {}
                 }
             }
-            write!(f, "{}", "\n")?;
+            writeln!(f)?;
         }
         Ok(())
     }
@@ -66,14 +66,14 @@ struct Image {
 }
 
 impl Image {
-    fn new(img_data: &Vec<char>, width: usize, height: usize) -> Self {
+    fn new(img_data: &[char], width: usize, height: usize) -> Self {
         let mut layers = vec![];
         let step = width * height;
         for i in (0..img_data.len()).step_by(step) {
             let layer = &img_data[i..(i + step)];
             layers.push(Layer::new(layer, width));
         }
-        Image { layers: layers }
+        Image { layers }
     }
 
     fn layers(&self) -> impl Iterator<Item = &Layer> {
@@ -106,12 +106,16 @@ impl Image {
 
 #[aoc_generator(day8)]
 fn parse_input(input: &str) -> Image {
-    Image::new(&input.chars().collect(), LAYER_WIDTH, LAYER_HEIGHT)
+    Image::new(
+        &input.chars().collect::<Vec<char>>(),
+        LAYER_WIDTH,
+        LAYER_HEIGHT,
+    )
 }
 
 #[aoc(day8, part1)]
 fn solve_p1(img: &Image) -> u32 {
-    let mut min_zeros = std::u32::MAX;
+    let mut min_zeros = u32::MAX;
     let mut one_times_two = 0;
     for layer in img.layers() {
         let (z, o, t) = layer.clone().count_pixels();
@@ -0,0 +1,978 @@
+use cache::DecodeCache;
+use extension::InstructionSet;
+use history::History;
+use memory::{DenseMemory, Memory};
+use profile::Profile;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use trace::TraceEntry;
+use watch::{CodeGuard, Watchpoints};
+use word::Word;
+
+pub mod ascii;
+pub mod asm;
+pub mod budget;
+mod cache;
+pub mod cfg;
+pub mod compile;
+pub mod debugger;
+pub mod decompile;
+pub mod disasm;
+pub mod extension;
+pub mod fuzz;
+pub mod history;
+pub mod io;
+pub mod memory;
+pub mod network;
+pub mod pipeline;
+pub mod profile;
+pub mod snapshot;
+pub mod symbolic;
+pub mod trace;
+pub mod watch;
+pub mod word;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parameter<W: Word = i64> {
+    Position(W),
+    Immediate(W),
+    Relative(W),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction<W: Word = i64> {
+    Add((Parameter<W>, Parameter<W>, Parameter<W>)),
+    Mul((Parameter<W>, Parameter<W>, Parameter<W>)),
+    Input(Parameter<W>),
+    Output(Parameter<W>),
+    JumpIfTrue((Parameter<W>, Parameter<W>)),
+    JumpIfFalse((Parameter<W>, Parameter<W>)),
+    LessThan((Parameter<W>, Parameter<W>, Parameter<W>)),
+    Equals((Parameter<W>, Parameter<W>, Parameter<W>)),
+    RelativeBaseOffset(Parameter<W>),
+    Halt,
+}
+
+impl<W: Word> fmt::Display for Parameter<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Parameter::Position(pos) => write!(f, "[{}]", pos),
+            Parameter::Immediate(val) => write!(f, "#{}", val),
+            Parameter::Relative(off) => {
+                let off = off.to_string();
+                match off.strip_prefix('-') {
+                    Some(magnitude) => write!(f, "rb-{}", magnitude),
+                    None => write!(f, "rb+{}", off),
+                }
+            }
+        }
+    }
+}
+
+impl<W: Word> Parameter<W> {
+    pub fn mode(&self) -> i64 {
+        match self {
+            Parameter::Position(_) => 0,
+            Parameter::Immediate(_) => 1,
+            Parameter::Relative(_) => 2,
+        }
+    }
+
+    pub fn word(&self) -> W {
+        match self {
+            Parameter::Position(word) | Parameter::Immediate(word) | Parameter::Relative(word) => {
+                word.clone()
+            }
+        }
+    }
+}
+
+impl<W: Word> Instruction<W> {
+    pub fn opcode(&self) -> i64 {
+        match self {
+            Instruction::Add(_) => 1,
+            Instruction::Mul(_) => 2,
+            Instruction::Input(_) => 3,
+            Instruction::Output(_) => 4,
+            Instruction::JumpIfTrue(_) => 5,
+            Instruction::JumpIfFalse(_) => 6,
+            Instruction::LessThan(_) => 7,
+            Instruction::Equals(_) => 8,
+            Instruction::RelativeBaseOffset(_) => 9,
+            Instruction::Halt => 99,
+        }
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Instruction::Add(_) => "ADD",
+            Instruction::Mul(_) => "MUL",
+            Instruction::Input(_) => "IN",
+            Instruction::Output(_) => "OUT",
+            Instruction::JumpIfTrue(_) => "JT",
+            Instruction::JumpIfFalse(_) => "JF",
+            Instruction::LessThan(_) => "LT",
+            Instruction::Equals(_) => "EQ",
+            Instruction::RelativeBaseOffset(_) => "ARB",
+            Instruction::Halt => "HLT",
+        }
+    }
+
+    pub fn params(&self) -> Vec<Parameter<W>> {
+        match self {
+            Instruction::Add((p1, p2, p3))
+            | Instruction::Mul((p1, p2, p3))
+            | Instruction::LessThan((p1, p2, p3))
+            | Instruction::Equals((p1, p2, p3)) => vec![p1.clone(), p2.clone(), p3.clone()],
+            Instruction::JumpIfTrue((p1, p2)) | Instruction::JumpIfFalse((p1, p2)) => {
+                vec![p1.clone(), p2.clone()]
+            }
+            Instruction::Input(p) | Instruction::Output(p) | Instruction::RelativeBaseOffset(p) => {
+                vec![p.clone()]
+            }
+            Instruction::Halt => vec![],
+        }
+    }
+
+    // Number of memory words the instruction occupies, opcode included
+    pub fn size(&self) -> usize {
+        match self {
+            Instruction::Add(_)
+            | Instruction::Mul(_)
+            | Instruction::LessThan(_)
+            | Instruction::Equals(_) => 4,
+            Instruction::JumpIfTrue(_) | Instruction::JumpIfFalse(_) => 3,
+            Instruction::Input(_) | Instruction::Output(_) | Instruction::RelativeBaseOffset(_) => {
+                2
+            }
+            Instruction::Halt => 1,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<W> {
+        let params = self.params();
+        let mut opcode = self.opcode();
+        for (n, param) in params.iter().enumerate() {
+            opcode += param.mode() * 10i64.pow(n as u32 + 2);
+        }
+        let mut words = vec![W::from_i64(opcode)];
+        words.extend(params.iter().map(Parameter::word));
+        words
+    }
+}
+
+impl<W: Word> fmt::Display for Instruction<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let params = self.params();
+        if params.is_empty() {
+            return write!(f, "{}", self.mnemonic());
+        }
+        let operands: Vec<String> = params.iter().map(|p| p.to_string()).collect();
+        write!(f, "{:<4}{}", self.mnemonic(), operands.join(", "))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Signal {
+    NeedsInput,
+    ProducedOutput,
+    Halt,
+    // Paused after an instruction that hit a watchpoint
+    Watchpoint,
+    // A run given a budget used it up, see `budget`
+    BudgetExhausted,
+    None,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum IntcodeError {
+    UnknownOpcode {
+        ip: usize,
+        opcode: i64,
+    },
+    UnknownParameterMode {
+        ip: usize,
+        opcode: i64,
+        mode: i64,
+    },
+    WriteToImmediate {
+        ip: usize,
+        opcode: i64,
+        operand: i64,
+    },
+    NegativeAddress {
+        ip: usize,
+        opcode: i64,
+        address: i64,
+    },
+    Overflow {
+        ip: usize,
+        opcode: i64,
+    },
+    WriteToCode {
+        ip: usize,
+        opcode: i64,
+        address: usize,
+    },
+    // Past what the memory backend can hold
+    AddressTooLarge {
+        ip: usize,
+        opcode: i64,
+        address: usize,
+    },
+    // Raised by a custom opcode
+    Extension {
+        ip: usize,
+        opcode: i64,
+        message: String,
+    },
+}
+
+impl fmt::Display for IntcodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IntcodeError::UnknownOpcode { ip, opcode } => {
+                write!(f, "unknown opcode {} at ip {}", opcode, ip)
+            }
+            IntcodeError::UnknownParameterMode { ip, opcode, mode } => write!(
+                f,
+                "unknown parameter mode {} in opcode {} at ip {}",
+                mode, opcode, ip
+            ),
+            IntcodeError::WriteToImmediate {
+                ip,
+                opcode,
+                operand,
+            } => write!(
+                f,
+                "can not store to immediate operand {} of opcode {} at ip {}",
+                operand, opcode, ip
+            ),
+            IntcodeError::NegativeAddress {
+                ip,
+                opcode,
+                address,
+            } => write!(
+                f,
+                "negative address {} used by opcode {} at ip {}",
+                address, opcode, ip
+            ),
+            IntcodeError::Overflow { ip, opcode } => {
+                write!(f, "value out of range in opcode {} at ip {}", opcode, ip)
+            }
+            IntcodeError::WriteToCode {
+                ip,
+                opcode,
+                address,
+            } => write!(
+                f,
+                "opcode {} at ip {} writes to code at address {}",
+                opcode, ip, address
+            ),
+            IntcodeError::AddressTooLarge {
+                ip,
+                opcode,
+                address,
+            } => write!(
+                f,
+                "address {} used by opcode {} at ip {} is past the end of memory",
+                address, opcode, ip
+            ),
+            IntcodeError::Extension {
+                ip,
+                opcode,
+                message,
+            } => write!(f, "{} in opcode {} at ip {}", message, opcode, ip),
+        }
+    }
+}
+
+impl Error for IntcodeError {}
+
+// A fault raised while executing an instruction, before the instruction's
+// ip and opcode are attached to it by `tick`
+enum Fault {
+    UnknownOpcode,
+    UnknownParameterMode(i64),
+    WriteToImmediate(i64),
+    NegativeAddress(i64),
+    Overflow,
+    WriteToCode(usize),
+    AddressTooLarge(usize),
+    // Boxed so that faults, and every result carrying one, stay two words.
+    // A `Box<str>` is twice that and measurably slows down the hot loop.
+    #[allow(clippy::box_collection)]
+    Extension(Box<String>),
+}
+
+impl Fault {
+    fn at(self, ip: usize, opcode: i64) -> IntcodeError {
+        match self {
+            Fault::UnknownOpcode => IntcodeError::UnknownOpcode { ip, opcode },
+            Fault::UnknownParameterMode(mode) => {
+                IntcodeError::UnknownParameterMode { ip, opcode, mode }
+            }
+            Fault::WriteToImmediate(operand) => IntcodeError::WriteToImmediate {
+                ip,
+                opcode,
+                operand,
+            },
+            Fault::NegativeAddress(address) => IntcodeError::NegativeAddress {
+                ip,
+                opcode,
+                address,
+            },
+            Fault::Overflow => IntcodeError::Overflow { ip, opcode },
+            Fault::WriteToCode(address) => IntcodeError::WriteToCode {
+                ip,
+                opcode,
+                address,
+            },
+            Fault::AddressTooLarge(address) => IntcodeError::AddressTooLarge {
+                ip,
+                opcode,
+                address,
+            },
+            Fault::Extension(message) => IntcodeError::Extension {
+                ip,
+                opcode,
+                message: *message,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct IntCodeComputer<M: Memory = DenseMemory> {
+    memory: M,
+    input: VecDeque<M::Word>,
+    output: Option<M::Word>,
+    relative_base_offset: i64,
+    ip: usize,
+    trace: Option<Vec<TraceEntry<M::Word>>>,
+    history: Option<History<M::Word>>,
+    profile: Option<Profile>,
+    cache: Option<DecodeCache<M::Word>>,
+    watchpoints: Option<Watchpoints<M::Word>>,
+    code_guard: Option<CodeGuard>,
+    extensions: Option<Arc<InstructionSet<M::Word>>>,
+    // Executed the HLT at ip
+    halted: bool,
+}
+
+// Values in errors are reported as i64, saturating words that do not fit
+fn saturate<W: Word>(word: &W) -> i64 {
+    match word.to_i64() {
+        Some(n) => n,
+        None if *word < W::default() => i64::MIN,
+        None => i64::MAX,
+    }
+}
+
+fn small<W: Word>(word: &W) -> Result<i64, Fault> {
+    word.to_i64().ok_or(Fault::Overflow)
+}
+
+impl<M: Memory> IntCodeComputer<M> {
+    pub fn new() -> Self {
+        IntCodeComputer::default()
+    }
+
+    pub fn load_memory(&mut self, memory: Vec<M::Word>) -> &mut Self {
+        self.memory = M::from_program(memory);
+        self.ip = 0;
+        self.relative_base_offset = 0;
+        self.halted = false;
+        if let Some(cache) = self.cache.as_mut() {
+            cache.clear();
+        }
+        self
+    }
+
+    // Keeps decoded instructions around instead of decoding them every time
+    // they run. Worth it for long-running programs, not for short ones that
+    // are run once.
+    pub fn enable_decode_cache(&mut self) -> &mut Self {
+        self.cache.get_or_insert_with(DecodeCache::default);
+        self
+    }
+
+    pub fn set_input(&mut self, input: Vec<M::Word>) -> &mut Self {
+        self.input = input.into();
+        self
+    }
+
+    pub fn get_value_at_pos(&self, i: usize) -> M::Word {
+        self.memory.get(i)
+    }
+
+    pub fn store_value_at_pos(&mut self, i: usize, value: M::Word) {
+        if let Some(cache) = self.cache.as_mut() {
+            cache.invalidate(i);
+        }
+        // Overwriting the HLT a machine halted on lets it run again
+        self.halted &= i != self.ip;
+        self.memory.set(i, value);
+    }
+
+    fn address(&self, i: i64) -> Result<usize, Fault> {
+        if i < 0 {
+            return Err(Fault::NegativeAddress(i));
+        }
+        Ok(i as usize)
+    }
+
+    fn relative_address(&self, offset: &M::Word) -> Result<usize, Fault> {
+        let pos = small(offset)?
+            .checked_add(self.relative_base_offset)
+            .ok_or(Fault::Overflow)?;
+        self.address(pos)
+    }
+
+    fn unwrap_value(&self, param: Parameter<M::Word>) -> Result<M::Word, Fault> {
+        match param {
+            Parameter::Immediate(val) => Ok(val),
+            Parameter::Position(pos) => Ok(self.get_value_at_pos(self.address(small(&pos)?)?)),
+            Parameter::Relative(pos) => Ok(self.get_value_at_pos(self.relative_address(&pos)?)),
+        }
+    }
+
+    // Returns the address written
+    fn store_val(&mut self, param: Parameter<M::Word>, val: M::Word) -> Result<usize, Fault> {
+        let pos = match param {
+            Parameter::Position(out) => self.address(small(&out)?)?,
+            Parameter::Relative(out) => self.relative_address(&out)?,
+            Parameter::Immediate(out) => return Err(Fault::WriteToImmediate(saturate(&out))),
+        };
+        if pos >= M::ADDRESS_LIMIT {
+            return Err(Fault::AddressTooLarge(pos));
+        }
+        if let Some(guard) = self.code_guard.as_ref() {
+            guard.check(pos)?;
+        }
+        if let Some(history) = self.history.as_mut() {
+            history.record_write(pos, self.memory.get(pos));
+        }
+        if let Some(entry) = self.trace.as_mut().and_then(|t| t.last_mut()) {
+            entry.writes.push((pos, val.clone()));
+        }
+        self.store_value_at_pos(pos, val);
+        Ok(pos)
+    }
+
+    fn emit_output(&mut self, param: Parameter<M::Word>) -> Result<(), Fault> {
+        self.output = Some(self.unwrap_value(param)?);
+        Ok(())
+    }
+
+    fn jump(&mut self, from: usize, param: Parameter<M::Word>) -> Result<(), Fault> {
+        let target = self.unwrap_value(param)?;
+        self.ip = self.address(small(&target)?)?;
+        if let Some(profile) = self.profile.as_mut() {
+            profile.record_jump(from, self.ip);
+        }
+        Ok(())
+    }
+
+    pub fn feed_input(&mut self, inp: M::Word) {
+        self.input.push_back(inp);
+    }
+
+    pub fn pending_input(&self) -> &VecDeque<M::Word> {
+        &self.input
+    }
+
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    pub fn relative_base(&self) -> i64 {
+        self.relative_base_offset
+    }
+
+    pub fn memory(&self) -> &M {
+        &self.memory
+    }
+
+    pub fn instruction_at(&self, addr: usize) -> Result<Instruction<M::Word>, IntcodeError> {
+        decode(|i| self.memory.get(i), addr)
+            .map_err(|fault| fault.at(addr, saturate(&self.memory.get(addr))))
+    }
+
+    pub fn enable_trace(&mut self) -> &mut Self {
+        self.trace.get_or_insert_with(Vec::new);
+        self
+    }
+
+    pub fn take_trace(&mut self) -> Vec<TraceEntry<M::Word>> {
+        self.trace.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    pub fn get_output(&mut self) -> Option<M::Word> {
+        self.output.take()
+    }
+
+    pub fn tick(&mut self) -> Result<Signal, IntcodeError> {
+        match self.cache {
+            Some(_) => self.step::<true>(),
+            None => self.step::<false>(),
+        }
+    }
+
+    fn step<const CACHED: bool>(&mut self) -> Result<Signal, IntcodeError> {
+        if let Some(watch) = self.watchpoints.as_mut() {
+            if watch.pause() {
+                return Ok(Signal::Watchpoint);
+            }
+        }
+        let ip = self.ip;
+        let traced = self.trace.as_ref().map(Vec::len);
+        let result = self.execute::<CACHED>();
+        if let Some(history) = self.history.as_mut() {
+            history.end(result.is_ok());
+        }
+        result.map_err(|fault| {
+            // Leave ip on the faulting instruction rather than skip it, and
+            // don't trace it as if it ran
+            self.ip = ip;
+            if let (Some(trace), Some(len)) = (self.trace.as_mut(), traced) {
+                trace.truncate(len);
+            }
+            fault.at(ip, saturate(&self.get_value_at_pos(ip)))
+        })
+    }
+
+    fn decode_cached(&mut self, ip: usize) -> Result<Instruction<M::Word>, Fault> {
+        let memory = &self.memory;
+        let cache = self.cache.get_or_insert_with(DecodeCache::default);
+        if let Some(inst) = cache.get(ip) {
+            return Ok(inst.clone());
+        }
+        let inst = decode(|i| memory.get(i), ip)?;
+        cache.insert(ip, inst.clone());
+        Ok(inst)
+    }
+
+    // Compiled once with and once without the decode cache. Which one to use
+    // is decided once per run rather than per instruction, see `run_while`.
+    fn execute<const CACHED: bool>(&mut self) -> Result<Signal, Fault> {
+        let ip = self.ip;
+        let decoded = match CACHED {
+            true => self.decode_cached(ip),
+            false => decode(|i| self.memory.get(i), ip),
+        };
+        let inst = match decoded {
+            Ok(inst) => inst,
+            Err(Fault::UnknownOpcode) if self.extensions.is_some() => {
+                return self.execute_extension(ip)
+            }
+            Err(fault) => return Err(fault),
+        };
+        match inst {
+            // Nothing runs once halted, so there is nothing to trace, profile
+            // or record either
+            Instruction::Halt if self.halted => return Ok(Signal::Halt),
+            // Leave ip on the IN so it is retried once input is fed
+            Instruction::Input(_) if self.input.is_empty() => return Ok(Signal::NeedsInput),
+            _ => {}
+        }
+        if self.trace.is_some() {
+            let operands = inst
+                .params()
+                .into_iter()
+                .map(|p| self.unwrap_value(p).unwrap_or_default())
+                .collect();
+            if let Some(trace) = self.trace.as_mut() {
+                trace.push(TraceEntry {
+                    ip: self.ip,
+                    instruction: inst.clone(),
+                    operands,
+                    writes: Vec::new(),
+                });
+            }
+        }
+        if let Some(history) = self.history.as_mut() {
+            history.begin(self.ip, self.relative_base_offset, self.output.clone());
+        }
+        if let Some(guard) = self.code_guard.as_mut() {
+            guard.executed(ip, inst.size());
+        }
+        let (reads, write) = self.watched_accesses(ip, &inst);
+        let mnemonic = inst.mnemonic();
+        self.ip += inst.size();
+        let signal = match inst {
+            Instruction::Add((param_1, param_2, param_3)) => {
+                let op1 = self.unwrap_value(param_1)?;
+                let op2 = self.unwrap_value(param_2)?;
+                self.store_val(param_3, op1.checked_add(&op2).ok_or(Fault::Overflow)?)?;
+                Signal::None
+            }
+            Instruction::Mul((param_1, param_2, param_3)) => {
+                let op1 = self.unwrap_value(param_1)?;
+                let op2 = self.unwrap_value(param_2)?;
+                self.store_val(param_3, op1.checked_mul(&op2).ok_or(Fault::Overflow)?)?;
+                Signal::None
+            }
+            Instruction::Input(param) => {
+                let inp = self.input.pop_front().unwrap();
+                if let Some(history) = self.history.as_mut() {
+                    history.record_input(inp.clone());
+                }
+                self.store_val(param, inp)?;
+                Signal::None
+            }
+            Instruction::Output(param) => {
+                self.emit_output(param)?;
+                Signal::ProducedOutput
+            }
+            Instruction::JumpIfTrue((param_1, param_2)) => {
+                if !self.unwrap_value(param_1)?.is_zero() {
+                    self.jump(ip, param_2)?;
+                }
+                Signal::None
+            }
+            Instruction::JumpIfFalse((param_1, param_2)) => {
+                if self.unwrap_value(param_1)?.is_zero() {
+                    self.jump(ip, param_2)?;
+                }
+                Signal::None
+            }
+            Instruction::LessThan((param_1, param_2, param_3)) => {
+                let op1 = self.unwrap_value(param_1)?;
+                let op2 = self.unwrap_value(param_2)?;
+                self.store_val(param_3, M::Word::from_i64(if op1 < op2 { 1 } else { 0 }))?;
+                Signal::None
+            }
+            Instruction::Equals((param_1, param_2, param_3)) => {
+                let op1 = self.unwrap_value(param_1)?;
+                let op2 = self.unwrap_value(param_2)?;
+                self.store_val(param_3, M::Word::from_i64(if op1 == op2 { 1 } else { 0 }))?;
+                Signal::None
+            }
+            Instruction::RelativeBaseOffset(offset) => {
+                let offset = small(&self.unwrap_value(offset)?)?;
+                self.relative_base_offset = self
+                    .relative_base_offset
+                    .checked_add(offset)
+                    .ok_or(Fault::Overflow)?;
+                Signal::None
+            }
+            Instruction::Halt => {
+                // Stay on the HLT, so a halted machine keeps reporting it
+                self.ip = ip;
+                self.halted = true;
+                Signal::Halt
+            }
+        };
+        // Counted once it has run, as one that faults didn't
+        if let Some(profile) = self.profile.as_mut() {
+            profile.record(ip, mnemonic);
+        }
+        if self.watchpoints.is_some() {
+            self.record_hits(ip, reads, write);
+        }
+        Ok(signal)
+    }
+
+    // Also stops where waiting any longer is pointless, on halt or when input
+    // runs out, and returns the signal it stopped at
+    pub fn run_till_signal(&mut self, signal: Signal) -> Result<Signal, IntcodeError> {
+        self.run_while(|s| *s != signal && *s != Signal::Halt && *s != Signal::NeedsInput)
+    }
+
+    pub fn run(&mut self) -> Result<Signal, IntcodeError> {
+        self.run_while(|s| *s == Signal::None)
+    }
+
+    fn run_while(
+        &mut self,
+        keep_going: impl FnMut(&Signal) -> bool,
+    ) -> Result<Signal, IntcodeError> {
+        match self.cache {
+            Some(_) => self.run_loop::<true>(keep_going),
+            None => self.run_loop::<false>(keep_going),
+        }
+    }
+
+    fn run_loop<const CACHED: bool>(
+        &mut self,
+        mut keep_going: impl FnMut(&Signal) -> bool,
+    ) -> Result<Signal, IntcodeError> {
+        loop {
+            let s = self.step::<CACHED>()?;
+            if !keep_going(&s) {
+                return Ok(s);
+            }
+        }
+    }
+
+    // Runs until the program halts or blocks on input, collecting every output
+    pub fn run_till_halt(&mut self) -> Result<Vec<M::Word>, IntcodeError> {
+        let mut outputs = Vec::new();
+        self.run_with_io(&mut VecDeque::new(), &mut outputs)?;
+        Ok(outputs)
+    }
+}
+
+fn get_parameter<W: Word>(word: W, mode: i64) -> Result<Parameter<W>, Fault> {
+    match mode {
+        0 => Ok(Parameter::Position(word)),
+        1 => Ok(Parameter::Immediate(word)),
+        2 => Ok(Parameter::Relative(word)),
+        _ => Err(Fault::UnknownParameterMode(mode)),
+    }
+}
+
+fn decode<W: Word>(word: impl Fn(usize) -> W, ip: usize) -> Result<Instruction<W>, Fault> {
+    let opcode = word(ip).to_i64().ok_or(Fault::UnknownOpcode)?;
+    let param = |n: usize| {
+        let mode = match n {
+            1 => opcode / 100,
+            2 => opcode / 1000,
+            _ => opcode / 10000,
+        };
+        get_parameter(word(ip + n), mode % 10)
+    };
+
+    Ok(match opcode % 100 {
+        1 => Instruction::Add((param(1)?, param(2)?, param(3)?)),
+        2 => Instruction::Mul((param(1)?, param(2)?, param(3)?)),
+        3 => Instruction::Input(param(1)?),
+        4 => Instruction::Output(param(1)?),
+        5 => Instruction::JumpIfTrue((param(1)?, param(2)?)),
+        6 => Instruction::JumpIfFalse((param(1)?, param(2)?)),
+        7 => Instruction::LessThan((param(1)?, param(2)?, param(3)?)),
+        8 => Instruction::Equals((param(1)?, param(2)?, param(3)?)),
+        9 => Instruction::RelativeBaseOffset(param(1)?),
+        99 => Instruction::Halt,
+        _ => return Err(Fault::UnknownOpcode),
+    })
+}
+
+pub fn get_instruction<W: Word>(mem: &[W], ip: usize) -> Result<Instruction<W>, IntcodeError> {
+    let word = |i: usize| mem.get(i).cloned().unwrap_or_default();
+    decode(word, ip).map_err(|fault| fault.at(ip, saturate(&word(ip))))
+}
+
+pub fn get_computer(mem: &[i64], input: Vec<i64>) -> IntCodeComputer {
+    let mut computer = IntCodeComputer::new();
+    computer.load_memory(mem.to_vec()).set_input(input);
+    computer
+}
+
+pub fn parse_program(input: &str) -> Vec<i64> {
+    parse_words(input)
+}
+
+// For programs that need a wider word than i64
+pub fn parse_words<W: Word>(input: &str) -> Vec<W> {
+    input
+        .trim()
+        .split(',')
+        .filter_map(|x| x.parse::<W>().ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_bigint::BigInt;
+
+    fn run_program(program: &str, input: Vec<i64>) -> Vec<i64> {
+        get_computer(&parse_program(program), input)
+            .run_till_halt()
+            .unwrap()
+    }
+
+    #[test]
+    fn day2_sample() {
+        let mut computer = get_computer(&parse_program("1,9,10,3,2,3,11,0,99,30,40,50"), vec![]);
+        computer.run_till_halt().unwrap();
+        assert_eq!(computer.get_value_at_pos(0), 3500)
+    }
+
+    #[test]
+    fn day5_immediate_mode() {
+        let mut computer = get_computer(&parse_program("1002,4,3,4,33"), vec![]);
+        computer.run_till_halt().unwrap();
+        assert_eq!(computer.get_value_at_pos(4), 99)
+    }
+
+    #[test]
+    fn day5_compare_to_8() {
+        let program = "3,9,8,9,10,9,4,9,99,-1,8";
+        assert_eq!(run_program(program, vec![8]), vec![1]);
+        assert_eq!(run_program(program, vec![7]), vec![0]);
+    }
+
+    #[test]
+    fn day9_quine() {
+        let program = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+        assert_eq!(run_program(program, vec![]), parse_program(program))
+    }
+
+    #[test]
+    fn day9_large_number() {
+        assert_eq!(
+            run_program("104,1125899906842624,99", vec![]),
+            vec![1125899906842624]
+        )
+    }
+
+    #[test]
+    fn decode_cache_sees_self_modification() {
+        // Outputs 1, 2, 3 by incrementing the operand of its own OUT
+        let program = parse_program("104,1,1001,1,1,1,1001,14,-1,14,1005,14,0,99,3");
+        let mut computer = get_computer(&program, vec![]);
+        computer.enable_decode_cache();
+        assert_eq!(computer.run_till_halt(), Ok(vec![1, 2, 3]))
+    }
+
+    #[test]
+    fn resumes_after_needs_input() {
+        let mut computer = get_computer(&parse_program("3,9,1001,9,1,9,4,9,99,0"), vec![]);
+        assert_eq!(computer.run(), Ok(Signal::NeedsInput));
+        computer.feed_input(41);
+        assert_eq!(computer.run_till_halt(), Ok(vec![42]))
+    }
+
+    #[test]
+    fn halt_is_sticky() {
+        // Garbage after the HLT is never decoded
+        let mut computer = get_computer(&parse_program("104,7,99,98,98"), vec![]);
+        assert_eq!(computer.run_till_halt(), Ok(vec![7]));
+        for _ in 0..3 {
+            assert_eq!(computer.tick(), Ok(Signal::Halt));
+            assert_eq!(computer.ip(), 2);
+        }
+        assert_eq!(computer.run(), Ok(Signal::Halt));
+    }
+
+    #[test]
+    fn halted_machines_do_nothing() {
+        let mut computer = get_computer(&parse_program("104,7,99"), vec![]);
+        computer.enable_trace().enable_profile().enable_history(10);
+        assert_eq!(computer.run_till_halt(), Ok(vec![7]));
+        for _ in 0..3 {
+            assert_eq!(computer.tick(), Ok(Signal::Halt));
+        }
+        assert_eq!(computer.take_trace().len(), 2);
+        assert_eq!(computer.profile().map(|p| p.cycles), Some(2));
+        assert_eq!(computer.history_len(), 2);
+        // Undoing the HLT makes it run again
+        assert!(computer.step_back());
+        assert_eq!(computer.tick(), Ok(Signal::Halt));
+        assert_eq!(computer.history_len(), 2);
+        // As does patching it
+        computer.store_value_at_pos(2, 104);
+        assert_eq!(computer.tick(), Ok(Signal::ProducedOutput));
+    }
+
+    #[test]
+    fn run_till_signal_gives_up() {
+        let mut computer = get_computer(&parse_program("1101,1,2,5,99,0"), vec![]);
+        assert_eq!(
+            computer.run_till_signal(Signal::ProducedOutput),
+            Ok(Signal::Halt)
+        );
+        let mut computer = get_computer(&parse_program("3,3,99,0"), vec![]);
+        assert_eq!(
+            computer.run_till_signal(Signal::ProducedOutput),
+            Ok(Signal::NeedsInput)
+        );
+    }
+
+    #[test]
+    fn unknown_opcode() {
+        let mut computer = get_computer(&parse_program("1101,1,2,5,98,0"), vec![]);
+        assert_eq!(
+            computer.run(),
+            Err(IntcodeError::UnknownOpcode { ip: 4, opcode: 98 })
+        )
+    }
+
+    #[test]
+    fn faults_leave_ip_on_the_instruction() {
+        let mut computer = get_computer(&parse_program("1101,1,1,-1,99"), vec![]);
+        let error = IntcodeError::NegativeAddress {
+            ip: 0,
+            opcode: 1101,
+            address: -1,
+        };
+        assert_eq!(computer.run(), Err(error));
+        assert_eq!(computer.ip(), 0);
+        // Patched, the write happens instead of being skipped
+        computer.store_value_at_pos(3, 0);
+        assert_eq!(computer.run(), Ok(Signal::Halt));
+        assert_eq!(computer.get_value_at_pos(0), 2);
+    }
+
+    #[test]
+    fn unknown_parameter_mode() {
+        let mut computer = get_computer(&parse_program("301,0,0,0,99"), vec![]);
+        assert_eq!(
+            computer.run(),
+            Err(IntcodeError::UnknownParameterMode {
+                ip: 0,
+                opcode: 301,
+                mode: 3
+            })
+        )
+    }
+
+    #[test]
+    fn write_to_immediate() {
+        let mut computer = get_computer(&parse_program("11101,1,2,7,99"), vec![]);
+        assert_eq!(
+            computer.run(),
+            Err(IntcodeError::WriteToImmediate {
+                ip: 0,
+                opcode: 11101,
+                operand: 7
+            })
+        )
+    }
+
+    #[test]
+    fn negative_address() {
+        let mut computer = get_computer(&parse_program("109,-5,204,2,99"), vec![]);
+        assert_eq!(
+            computer.run(),
+            Err(IntcodeError::NegativeAddress {
+                ip: 2,
+                opcode: 204,
+                address: -3
+            })
+        )
+    }
+
+    // Squares its input
+    const SQUARE: &str = "3,9,2,9,9,9,4,9,99,0";
+
+    #[test]
+    fn overflow() {
+        let mut computer = get_computer(&parse_program(SQUARE), vec![1 << 32]);
+        assert_eq!(
+            computer.run(),
+            Err(IntcodeError::Overflow { ip: 2, opcode: 2 })
+        )
+    }
+
+    #[test]
+    fn wide_words() {
+        let mut computer = IntCodeComputer::<DenseMemory<i128>>::new();
+        computer
+            .load_memory(parse_words(SQUARE))
+            .set_input(vec![1 << 32]);
+        assert_eq!(computer.run_till_halt(), Ok(vec![1 << 64]));
+
+        let big = BigInt::from(1) << 64usize;
+        let mut computer = IntCodeComputer::<DenseMemory<BigInt>>::new();
+        computer
+            .load_memory(parse_words(SQUARE))
+            .set_input(vec![big.clone()]);
+        assert_eq!(computer.run_till_halt(), Ok(vec![&big * &big]));
+    }
+}
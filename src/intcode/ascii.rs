@@ -0,0 +1,149 @@
+// Text I/O for programs that talk in ASCII. Input is sent a line at a time,
+// output is collected as text, and the last value outside the ASCII range
+// (usually the puzzle answer) is kept separately.
+
+use super::io::OutputSink;
+use super::memory::{DenseMemory, Memory};
+use super::{IntCodeComputer, IntcodeError, Signal};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::io::{BufRead, Write};
+
+pub fn encode_line(line: &str) -> Vec<i64> {
+    line.chars().chain(Some('\n')).map(|c| c as i64).collect()
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct AsciiOutput {
+    pub text: String,
+    pub answer: Option<i64>,
+}
+
+impl OutputSink for AsciiOutput {
+    fn emit(&mut self, value: i64) {
+        match value {
+            0..=127 => self.text.push(value as u8 as char),
+            _ => self.answer = Some(value),
+        }
+    }
+}
+
+pub struct AsciiComputer<M: Memory = DenseMemory> {
+    computer: IntCodeComputer<M>,
+    pending: VecDeque<i64>,
+}
+
+impl<M: Memory<Word = i64>> AsciiComputer<M> {
+    pub fn new(computer: IntCodeComputer<M>) -> Self {
+        AsciiComputer {
+            computer,
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub fn computer(&self) -> &IntCodeComputer<M> {
+        &self.computer
+    }
+
+    pub fn send_line(&mut self, line: &str) -> &mut Self {
+        self.pending.extend(encode_line(line));
+        self
+    }
+
+    // Runs until the program halts or wants a line that has not been sent
+    pub fn run(&mut self) -> Result<(Signal, AsciiOutput), IntcodeError> {
+        let mut output = AsciiOutput::default();
+        let signal = self.computer.run_with_io(&mut self.pending, &mut output)?;
+        Ok((signal, output))
+    }
+
+    // Sends every line in `script` and returns all the output
+    pub fn run_script(&mut self, script: &[&str]) -> Result<AsciiOutput, IntcodeError> {
+        for line in script {
+            self.send_line(line);
+        }
+        Ok(self.run()?.1)
+    }
+
+    // Pipes the program's text to `out` and lines from `input` back to it,
+    // until it halts or `input` runs out. Returns the non-ASCII answer, if any.
+    pub fn interact(
+        &mut self,
+        mut input: impl BufRead,
+        mut out: impl Write,
+    ) -> Result<Option<i64>, Box<dyn Error>> {
+        let mut answer = None;
+        loop {
+            let (signal, output) = self.run()?;
+            write!(out, "{}", output.text)?;
+            out.flush()?;
+            answer = output.answer.or(answer);
+            if signal == Signal::Halt {
+                return Ok(answer);
+            }
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(answer);
+            }
+            self.send_line(line.trim_end_matches(['\r', '\n']));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::asm::assemble;
+    use crate::intcode::{get_computer, parse_program};
+
+    // Prints a prompt, echoes one line back, then outputs 1000 and halts
+    const ECHO: &str = "
+                OUT  #63
+                OUT  #10
+        loop:   IN   [c]
+                OUT  [c]
+                EQ   [c], #10, [t]
+                JF   [t], #loop
+                OUT  #1000
+                HLT
+        c:      .data 0
+        t:      .data 0
+    ";
+
+    fn echo() -> AsciiComputer {
+        AsciiComputer::new(get_computer(&assemble(ECHO).unwrap(), vec![]))
+    }
+
+    #[test]
+    fn separates_text_from_answer() {
+        let output = echo().run_script(&["hi"]).unwrap();
+        assert_eq!(
+            output,
+            AsciiOutput {
+                text: "?\nhi\n".to_string(),
+                answer: Some(1000)
+            }
+        );
+    }
+
+    #[test]
+    fn keeps_the_last_answer() {
+        let program = parse_program("104,5000,104,65,104,7000,104,10,99");
+        let mut computer = AsciiComputer::new(get_computer(&program, vec![]));
+        assert_eq!(
+            computer.run_script(&[]),
+            Ok(AsciiOutput {
+                text: "A\n".to_string(),
+                answer: Some(7000)
+            })
+        );
+    }
+
+    #[test]
+    fn interactive_session() {
+        let mut screen = Vec::new();
+        let answer = echo().interact(&b"hello\n"[..], &mut screen).unwrap();
+        assert_eq!(answer, Some(1000));
+        assert_eq!(String::from_utf8(screen).unwrap(), "?\nhello\n");
+    }
+}
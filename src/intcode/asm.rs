@@ -0,0 +1,306 @@
+// Assembler for a small Intcode assembly dialect, the inverse of `disasm`.
+//
+//     ; comments run to the end of the line
+//     loop:   IN   [x]
+//             JF   [x], #done
+//             ADD  [x], [total], [total]
+//             JT   #1, #loop
+//     done:   OUT  [total]
+//             HLT
+//     x:      .data 0
+//     total:  .data 0
+//
+// Operands are `#imm`, `[pos]` or `rb+off`, and anywhere a number is expected
+// a label (optionally `label+N` / `label-N`) can be used instead.
+
+use super::{Instruction, Parameter};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AsmError {}
+
+enum Value {
+    Number(i64),
+    Label(String, i64),
+}
+
+struct Operand {
+    mode: i64,
+    value: Value,
+}
+
+enum Statement {
+    Instruction(String, Vec<Operand>),
+    Data(Vec<Value>),
+}
+
+fn is_label(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+fn parse_value(s: &str) -> Result<Value, String> {
+    let s = s.trim();
+    if let Ok(n) = s.parse::<i64>() {
+        return Ok(Value::Number(n));
+    }
+    let (label, offset) = match s.rfind(['+', '-']) {
+        Some(i) if i > 0 => {
+            let offset = s[i..]
+                .replace('+', "")
+                .trim()
+                .parse::<i64>()
+                .map_err(|_| format!("bad offset in `{}`", s))?;
+            (s[..i].trim(), offset)
+        }
+        _ => (s, 0),
+    };
+    if !is_label(label) {
+        return Err(format!("expected a number or label, found `{}`", s));
+    }
+    Ok(Value::Label(label.to_string(), offset))
+}
+
+fn parse_operand(s: &str) -> Result<Operand, String> {
+    let s = s.trim();
+    if let Some(rest) = s.strip_prefix('#') {
+        return Ok(Operand {
+            mode: 1,
+            value: parse_value(rest)?,
+        });
+    }
+    if let Some(rest) = s.strip_prefix('[') {
+        let inner = rest
+            .strip_suffix(']')
+            .ok_or_else(|| format!("unclosed `[` in `{}`", s))?;
+        return Ok(Operand {
+            mode: 0,
+            value: parse_value(inner)?,
+        });
+    }
+    if let Some(rest) = s.strip_prefix("rb") {
+        let rest = rest.trim();
+        let value = match rest.strip_prefix('+') {
+            _ if rest.is_empty() => Value::Number(0),
+            Some(off) => parse_value(off)?,
+            None if rest.starts_with('-') => parse_value(rest)?,
+            None => return Err(format!("bad relative operand `{}`", s)),
+        };
+        return Ok(Operand { mode: 2, value });
+    }
+    Err(format!(
+        "operand `{}` must be written as #imm, [pos] or rb+off",
+        s
+    ))
+}
+
+fn arity(mnemonic: &str) -> Option<usize> {
+    match mnemonic {
+        "ADD" | "MUL" | "LT" | "EQ" => Some(3),
+        "JT" | "JF" => Some(2),
+        "IN" | "OUT" | "ARB" => Some(1),
+        "HLT" => Some(0),
+        _ => None,
+    }
+}
+
+fn build(mnemonic: &str, p: &[Parameter]) -> Instruction {
+    match (mnemonic, p) {
+        ("ADD", &[a, b, c]) => Instruction::Add((a, b, c)),
+        ("MUL", &[a, b, c]) => Instruction::Mul((a, b, c)),
+        ("LT", &[a, b, c]) => Instruction::LessThan((a, b, c)),
+        ("EQ", &[a, b, c]) => Instruction::Equals((a, b, c)),
+        ("JT", &[a, b]) => Instruction::JumpIfTrue((a, b)),
+        ("JF", &[a, b]) => Instruction::JumpIfFalse((a, b)),
+        ("IN", &[a]) => Instruction::Input(a),
+        ("OUT", &[a]) => Instruction::Output(a),
+        ("ARB", &[a]) => Instruction::RelativeBaseOffset(a),
+        _ => Instruction::Halt,
+    }
+}
+
+fn parse_statement(text: &str) -> Result<Statement, String> {
+    if let Some(rest) = text.strip_prefix(".data") {
+        let values = rest
+            .split(',')
+            .map(parse_value)
+            .collect::<Result<Vec<_>, _>>()?;
+        return Ok(Statement::Data(values));
+    }
+
+    let (mnemonic, rest) = match text.find(char::is_whitespace) {
+        Some(i) => (&text[..i], text[i..].trim()),
+        None => (text, ""),
+    };
+    let mnemonic = mnemonic.to_uppercase();
+    let expected = arity(&mnemonic).ok_or_else(|| format!("unknown mnemonic `{}`", mnemonic))?;
+    let operands = if rest.is_empty() {
+        Vec::new()
+    } else {
+        rest.split(',')
+            .map(parse_operand)
+            .collect::<Result<Vec<_>, _>>()?
+    };
+    if operands.len() != expected {
+        return Err(format!(
+            "{} takes {} operands, found {}",
+            mnemonic,
+            expected,
+            operands.len()
+        ));
+    }
+    Ok(Statement::Instruction(mnemonic, operands))
+}
+
+fn resolve(value: &Value, labels: &HashMap<String, i64>) -> Result<i64, String> {
+    match value {
+        Value::Number(n) => Ok(*n),
+        Value::Label(name, offset) => {
+            let addr = labels
+                .get(name)
+                .ok_or_else(|| format!("undefined label `{}`", name))?;
+            addr.checked_add(*offset)
+                .ok_or_else(|| format!("`{}{:+}` is out of range", name, offset))
+        }
+    }
+}
+
+pub fn assemble(source: &str) -> Result<Vec<i64>, AsmError> {
+    let mut labels = HashMap::new();
+    let mut statements = Vec::new();
+    let mut address = 0;
+
+    for (n, line) in source.lines().enumerate() {
+        let error = |message| AsmError {
+            line: n + 1,
+            message,
+        };
+        let mut text = line.split(';').next().unwrap().trim();
+        while let Some(i) = text.find(':') {
+            let label = text[..i].trim();
+            if !is_label(label) {
+                break;
+            }
+            if labels.insert(label.to_string(), address).is_some() {
+                return Err(error(format!("label `{}` defined twice", label)));
+            }
+            text = text[i + 1..].trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+        let statement = parse_statement(text).map_err(error)?;
+        address += match &statement {
+            Statement::Instruction(_, operands) => operands.len() as i64 + 1,
+            Statement::Data(values) => values.len() as i64,
+        };
+        statements.push((n + 1, statement));
+    }
+
+    let mut program = Vec::new();
+    for (line, statement) in statements {
+        let error = |message| AsmError { line, message };
+        match statement {
+            Statement::Instruction(mnemonic, operands) => {
+                let mut params = Vec::new();
+                for operand in operands {
+                    let word = resolve(&operand.value, &labels).map_err(error)?;
+                    params.push(match operand.mode {
+                        0 => Parameter::Position(word),
+                        1 => Parameter::Immediate(word),
+                        _ => Parameter::Relative(word),
+                    });
+                }
+                program.extend(build(&mnemonic, &params).encode());
+            }
+            Statement::Data(values) => {
+                for value in values {
+                    program.push(resolve(&value, &labels).map_err(error)?);
+                }
+            }
+        }
+    }
+    Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::get_computer;
+
+    #[test]
+    fn encodes_modes() {
+        assert_eq!(
+            assemble("MUL [4], #3, rb-4\nhlt").unwrap(),
+            vec![21002, 4, 3, -4, 99]
+        )
+    }
+
+    #[test]
+    fn sums_until_zero() {
+        let source = "
+            loop:   IN   [x]
+                    JF   [x], #done
+                    ADD  [x], [total], [total]
+                    JT   #1, #loop
+            done:   OUT  [total]
+                    HLT
+            x:      .data 0
+            total:  .data 0
+        ";
+        let program = assemble(source).unwrap();
+        let mut computer = get_computer(&program, vec![3, 4, 5, 0]);
+        assert_eq!(computer.run_till_halt().unwrap(), vec![12])
+    }
+
+    #[test]
+    fn label_offsets() {
+        assert_eq!(
+            assemble("OUT [buf+1]\nHLT\nbuf: .data 7, 8").unwrap(),
+            vec![4, 4, 99, 7, 8]
+        )
+    }
+
+    #[test]
+    fn reports_line_of_error() {
+        assert_eq!(
+            assemble("IN [x]\nJT #1, #nowhere\nx: .data 0"),
+            Err(AsmError {
+                line: 2,
+                message: "undefined label `nowhere`".to_string()
+            })
+        )
+    }
+
+    #[test]
+    fn reports_label_offsets_out_of_range() {
+        assert_eq!(
+            assemble(
+                "HLT
+x: .data x+9223372036854775807"
+            ),
+            Err(AsmError {
+                line: 2,
+                message: "`x+9223372036854775807` is out of range".to_string()
+            })
+        )
+    }
+}
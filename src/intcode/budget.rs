@@ -0,0 +1,178 @@
+// Limits on how long a program may run. A `Budget` caps the number of
+// instructions, sets a wall-clock deadline, or both:
+//
+//     let mut budget = Budget::instructions(1_000_000).with_timeout(Duration::from_secs(1));
+//     match computer.run_within(&mut budget)? {
+//         Signal::BudgetExhausted => ...,
+//         signal => ...,
+//     }
+//
+// A budget is used up across calls, so the same one can be handed to each
+// resume of a machine that pauses for input or output. Running out leaves the
+// machine before its next instruction, and it can be run on with a new budget.
+//
+// Reading the clock costs more than an instruction, so the deadline is only
+// looked at every `CLOCK_EVERY` instructions and can be overshot by that many.
+
+use super::io::{InputSource, OutputSink};
+use super::memory::Memory;
+use super::{IntCodeComputer, IntcodeError, Signal};
+use std::time::{Duration, Instant};
+
+const CLOCK_EVERY: u64 = 1024;
+
+#[derive(Debug, Clone, Default)]
+pub struct Budget {
+    instructions: Option<u64>,
+    deadline: Option<Instant>,
+    spent: u64,
+    // The deadline has passed
+    expired: bool,
+}
+
+impl Budget {
+    pub fn unlimited() -> Self {
+        Budget::default()
+    }
+
+    pub fn instructions(n: u64) -> Self {
+        Budget::unlimited().with_instructions(n)
+    }
+
+    pub fn deadline(at: Instant) -> Self {
+        Budget::unlimited().with_deadline(at)
+    }
+
+    pub fn timeout(after: Duration) -> Self {
+        Budget::unlimited().with_timeout(after)
+    }
+
+    pub fn with_instructions(mut self, n: u64) -> Self {
+        self.instructions = Some(n);
+        self
+    }
+
+    pub fn with_deadline(mut self, at: Instant) -> Self {
+        self.deadline = Some(at);
+        self
+    }
+
+    pub fn with_timeout(self, after: Duration) -> Self {
+        self.with_deadline(Instant::now() + after)
+    }
+
+    // Instructions executed so far
+    pub fn spent(&self) -> u64 {
+        self.spent
+    }
+
+    pub fn is_exhausted(&mut self) -> bool {
+        self.check(true)
+    }
+
+    // Counts an instruction, and says whether it was the last one allowed
+    fn spend(&mut self) -> bool {
+        self.spent += 1;
+        self.check(self.spent.is_multiple_of(CLOCK_EVERY))
+    }
+
+    fn check(&mut self, read_clock: bool) -> bool {
+        if let Some(deadline) = self.deadline.filter(|_| read_clock) {
+            self.expired |= Instant::now() >= deadline;
+        }
+        self.expired || self.instructions.is_some_and(|n| self.spent >= n)
+    }
+}
+
+impl<M: Memory> IntCodeComputer<M> {
+    // Like `run`, but stops with `Signal::BudgetExhausted` once the budget is
+    // used up
+    pub fn run_within(&mut self, budget: &mut Budget) -> Result<Signal, IntcodeError> {
+        if budget.is_exhausted() {
+            return Ok(Signal::BudgetExhausted);
+        }
+        // Waiting for input executes nothing
+        let signal = self.run_while(|s| {
+            let last = *s != Signal::NeedsInput && budget.spend();
+            *s == Signal::None && !last
+        })?;
+        Ok(match signal {
+            Signal::None => Signal::BudgetExhausted,
+            signal => signal,
+        })
+    }
+
+    // Like `run_with_io`, with the budget shared by every run in between IO
+    pub fn run_with_io_within(
+        &mut self,
+        input: &mut impl InputSource<M::Word>,
+        output: &mut impl OutputSink<M::Word>,
+        budget: &mut Budget,
+    ) -> Result<Signal, IntcodeError> {
+        self.pump(input, output, |computer| computer.run_within(budget))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{get_computer, parse_program};
+    use std::collections::VecDeque;
+
+    // Counts up in [7] forever
+    const FOREVER: &str = "101,1,7,7,1105,1,0,0";
+
+    #[test]
+    fn instruction_budget() {
+        let mut computer = get_computer(&parse_program(FOREVER), vec![]);
+        let mut budget = Budget::instructions(11);
+        assert_eq!(
+            computer.run_within(&mut budget),
+            Ok(Signal::BudgetExhausted)
+        );
+        assert_eq!((budget.spent(), computer.get_value_at_pos(7)), (11, 6));
+        // Spent for good, but the machine can go on with a new budget
+        assert_eq!(
+            computer.run_within(&mut budget),
+            Ok(Signal::BudgetExhausted)
+        );
+        assert_eq!(budget.spent(), 11);
+        computer.run_within(&mut Budget::instructions(2)).unwrap();
+        assert_eq!(computer.get_value_at_pos(7), 7);
+    }
+
+    #[test]
+    fn deadline() {
+        let mut computer = get_computer(&parse_program(FOREVER), vec![]);
+        let mut budget = Budget::timeout(Duration::from_millis(10));
+        assert_eq!(
+            computer.run_within(&mut budget),
+            Ok(Signal::BudgetExhausted)
+        );
+        assert!(budget.spent() > 0 && budget.is_exhausted());
+        let mut budget = Budget::deadline(Instant::now());
+        assert_eq!(
+            computer.run_within(&mut budget),
+            Ok(Signal::BudgetExhausted)
+        );
+        assert_eq!(budget.spent(), 0);
+    }
+
+    #[test]
+    fn budget_spans_io() {
+        // Echoes inputs doubled until it reads a zero
+        let program = parse_program("3,15,1006,15,14,102,2,15,15,4,15,1105,1,0,99,0");
+        let mut computer = get_computer(&program, vec![]);
+        let mut input = VecDeque::from(vec![1, 2, 3, 0]);
+        let mut outputs = Vec::new();
+        let mut budget = Budget::instructions(10);
+        let signal = computer.run_with_io_within(&mut input, &mut outputs, &mut budget);
+        assert_eq!(signal, Ok(Signal::BudgetExhausted));
+        assert_eq!(outputs, vec![2, 4]);
+
+        let mut budget = Budget::unlimited().with_timeout(Duration::from_secs(60));
+        let signal = computer.run_with_io_within(&mut input, &mut outputs, &mut budget);
+        assert_eq!(signal, Ok(Signal::Halt));
+        assert_eq!((outputs, budget.spent()), (vec![2, 4, 6], 8));
+    }
+}
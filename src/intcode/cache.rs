@@ -0,0 +1,69 @@
+// Decoded instructions keyed by address, so a loop only pays for decoding
+// once. A write anywhere inside a cached instruction drops it, which keeps
+// self-modifying programs working.
+//
+// Filling the cache costs more than decoding once, so it only pays off on
+// long runs that execute the same instructions over and over. The cache is
+// indexed by address, and code past `MAX_CACHED` is decoded every time
+// rather than growing it without bound.
+
+use super::word::Word;
+use super::Instruction;
+
+// Longest instruction, opcode included
+const MAX_SIZE: usize = 4;
+
+const MAX_CACHED: usize = 1 << 16;
+
+#[derive(Clone, Default)]
+pub struct DecodeCache<W: Word> {
+    // Below MAX_CACHED only
+    entries: Vec<Option<Instruction<W>>>,
+}
+
+impl<W: Word> DecodeCache<W> {
+    pub(super) fn get(&self, ip: usize) -> Option<&Instruction<W>> {
+        self.entries.get(ip)?.as_ref()
+    }
+
+    pub(super) fn insert(&mut self, ip: usize, inst: Instruction<W>) {
+        if ip >= MAX_CACHED {
+            return;
+        }
+        if self.entries.len() <= ip {
+            self.entries.resize(ip + 1, None);
+        }
+        self.entries[ip] = Some(inst);
+    }
+
+    pub(super) fn invalidate(&mut self, addr: usize) {
+        let end = (addr + 1).min(self.entries.len());
+        let start = (addr + 1).saturating_sub(MAX_SIZE).min(end);
+        for entry in &mut self.entries[start..end] {
+            *entry = None;
+        }
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intcode::memory::SparseMemory;
+    use crate::intcode::{parse_program, IntCodeComputer};
+
+    #[test]
+    fn far_code_is_not_cached() {
+        // Jumps to 2^40, which outputs 7 and halts
+        let mut computer = IntCodeComputer::<SparseMemory>::new();
+        computer.load_memory(parse_program("1105,1,1099511627776"));
+        for (i, &word) in [104, 7, 99].iter().enumerate() {
+            computer.store_value_at_pos((1 << 40) + i, word);
+        }
+        computer.enable_decode_cache();
+        assert_eq!(computer.run_till_halt(), Ok(vec![7]));
+        assert_eq!(computer.cache.as_ref().map(|c| c.entries.len()), Some(1));
+    }
+}
@@ -0,0 +1,354 @@
+// Control-flow graph recovered statically from a program image. Code is found
+// by following execution from address 0 rather than by a linear sweep, so
+// data tables don't turn into bogus blocks.
+//
+// Jumps with an immediate target are followed. A jump whose target is read
+// from memory (returns, jump tables) can't be resolved without running the
+// program, so its block is flagged as ending in an indirect jump instead.
+// Calls are recognised by the relative base convention: the return address
+// is stored as a constant to a relative slot right before an unconditional
+// jump, so the code after the jump is reachable too.
+
+use super::{get_instruction, Instruction, Parameter};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    // Runs straight into the block at the given address
+    Fallthrough(usize),
+    Jump(usize),
+    Branch { taken: usize, not_taken: usize },
+    Call { target: usize, ret: usize },
+    // The target is only known at runtime
+    Indirect { not_taken: Option<usize> },
+    Halt,
+    // Runs into something that doesn't decode, or off the end of the program
+    Invalid,
+}
+
+impl Exit {
+    pub fn successors(&self) -> Vec<usize> {
+        match *self {
+            Exit::Fallthrough(next) | Exit::Jump(next) => vec![next],
+            Exit::Branch { taken, not_taken } => vec![taken, not_taken],
+            Exit::Call { target, ret } => vec![target, ret],
+            Exit::Indirect { not_taken } => not_taken.into_iter().collect(),
+            Exit::Halt | Exit::Invalid => vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub start: usize,
+    // One past the last word of the block
+    pub end: usize,
+    pub instructions: Vec<(usize, Instruction)>,
+    pub exit: Exit,
+}
+
+// An instruction writing to a fixed address inside reachable code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodeWrite {
+    pub ip: usize,
+    pub address: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cfg {
+    pub blocks: BTreeMap<usize, Block>,
+    pub code_writes: Vec<CodeWrite>,
+}
+
+fn decode(program: &[i64], ip: usize) -> Option<Instruction> {
+    match get_instruction(program, ip) {
+        Ok(inst) if ip + inst.size() <= program.len() => Some(inst),
+        _ => None,
+    }
+}
+
+fn immediate(param: &Parameter) -> Option<i64> {
+    match param {
+        Parameter::Immediate(value) => Some(*value),
+        _ => None,
+    }
+}
+
+fn target(param: &Parameter) -> Option<usize> {
+    immediate(param).filter(|&t| t >= 0).map(|t| t as usize)
+}
+
+// Constant stored to a relative slot, as when pushing a return address
+fn pushed_constant(inst: &Instruction) -> Option<i64> {
+    match inst {
+        Instruction::Add((a, b, Parameter::Relative(_))) => {
+            immediate(a)?.checked_add(immediate(b)?)
+        }
+        Instruction::Mul((a, b, Parameter::Relative(_))) => {
+            immediate(a)?.checked_mul(immediate(b)?)
+        }
+        _ => None,
+    }
+}
+
+fn write_destination(inst: &Instruction) -> Option<&Parameter> {
+    match inst {
+        Instruction::Add((_, _, dst))
+        | Instruction::Mul((_, _, dst))
+        | Instruction::LessThan((_, _, dst))
+        | Instruction::Equals((_, _, dst))
+        | Instruction::Input(dst) => Some(dst),
+        _ => None,
+    }
+}
+
+// How the instruction at `ip` ends a block, if it does. `prev` is the
+// instruction right before it.
+fn exit_of(prev: Option<&Instruction>, ip: usize, inst: &Instruction) -> Option<Exit> {
+    let next = ip + inst.size();
+    let (cond, dest, jump_if) = match inst {
+        Instruction::JumpIfTrue((cond, dest)) => (cond, dest, true),
+        Instruction::JumpIfFalse((cond, dest)) => (cond, dest, false),
+        Instruction::Halt => return Some(Exit::Halt),
+        _ => return None,
+    };
+    let always = match immediate(cond) {
+        Some(value) if (value != 0) != jump_if => return None,
+        Some(_) => true,
+        None => false,
+    };
+    Some(match (target(dest), always) {
+        (Some(target), true) => match prev.and_then(pushed_constant) {
+            Some(ret) if ret == next as i64 => Exit::Call { target, ret: next },
+            _ => Exit::Jump(target),
+        },
+        (Some(taken), false) => Exit::Branch {
+            taken,
+            not_taken: next,
+        },
+        (None, true) => Exit::Indirect { not_taken: None },
+        (None, false) => Exit::Indirect {
+            not_taken: Some(next),
+        },
+    })
+}
+
+pub fn build(program: &[i64]) -> Cfg {
+    let mut code: BTreeMap<usize, Instruction> = BTreeMap::new();
+    let mut exits = BTreeMap::new();
+    let mut leaders = BTreeSet::new();
+    let mut pending = vec![0];
+    while let Some(start) = pending.pop() {
+        leaders.insert(start);
+        let mut ip = start;
+        let mut prev = None;
+        while !code.contains_key(&ip) {
+            let inst = match decode(program, ip) {
+                Some(inst) => inst,
+                None => break,
+            };
+            code.insert(ip, inst);
+            if let Some(exit) = exit_of(prev.as_ref(), ip, &inst) {
+                exits.insert(ip, exit);
+                pending.extend(exit.successors());
+                break;
+            }
+            prev = Some(inst);
+            ip += inst.size();
+        }
+    }
+
+    let mut blocks = BTreeMap::new();
+    for &start in &leaders {
+        let mut ip = start;
+        let mut instructions = Vec::new();
+        let exit = loop {
+            let inst = match code.get(&ip) {
+                Some(inst) => *inst,
+                None => break Exit::Invalid,
+            };
+            instructions.push((ip, inst));
+            if let Some(exit) = exits.get(&ip) {
+                break *exit;
+            }
+            ip += inst.size();
+            if leaders.contains(&ip) {
+                break Exit::Fallthrough(ip);
+            }
+        };
+        if let Some(&(last, inst)) = instructions.last() {
+            let end = last + inst.size();
+            blocks.insert(
+                start,
+                Block {
+                    start,
+                    end,
+                    instructions,
+                    exit,
+                },
+            );
+        }
+    }
+
+    let code_words: BTreeSet<usize> = code
+        .iter()
+        .flat_map(|(&ip, inst)| ip..ip + inst.size())
+        .collect();
+    let code_writes = code
+        .iter()
+        .filter_map(|(&ip, inst)| match write_destination(inst) {
+            Some(&Parameter::Position(address))
+                if address >= 0 && code_words.contains(&(address as usize)) =>
+            {
+                Some(CodeWrite {
+                    ip,
+                    address: address as usize,
+                })
+            }
+            _ => None,
+        })
+        .collect();
+
+    Cfg {
+        blocks,
+        code_writes,
+    }
+}
+
+impl Cfg {
+    pub fn block_containing(&self, addr: usize) -> Option<&Block> {
+        let (_, block) = self.blocks.range(..=addr).next_back()?;
+        Some(block).filter(|b| addr < b.end)
+    }
+
+    pub fn predecessors(&self, start: usize) -> Vec<usize> {
+        self.blocks
+            .values()
+            .filter(|b| b.exit.successors().contains(&start))
+            .map(|b| b.start)
+            .collect()
+    }
+
+    // Blocks ending in an indirect jump are drawn red, blocks that write into
+    // code are filled
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph intcode {\n    node [shape=box fontname=monospace];\n");
+        for block in self.blocks.values() {
+            let mut label = String::new();
+            for (ip, inst) in &block.instructions {
+                write!(label, "{:>6}  {}\\l", ip, inst).unwrap();
+            }
+            let mut attrs = String::new();
+            if let Exit::Indirect { .. } = block.exit {
+                attrs += " color=red";
+            }
+            if self
+                .code_writes
+                .iter()
+                .any(|w| block.start <= w.ip && w.ip < block.end)
+            {
+                attrs += " style=filled fillcolor=orange";
+            }
+            writeln!(dot, "    b{} [label=\"{}\"{}];", block.start, label, attrs).unwrap();
+
+            let edge = |to: usize, label: &str| {
+                let attrs = match label {
+                    "" => String::new(),
+                    "ret" => " [label=ret style=dashed]".to_string(),
+                    _ => format!(" [label={}]", label),
+                };
+                format!("    b{} -> b{}{};\n", block.start, to, attrs)
+            };
+            match block.exit {
+                Exit::Fallthrough(to) | Exit::Jump(to) => dot += &edge(to, ""),
+                Exit::Branch { taken, not_taken } => {
+                    dot += &edge(taken, "T");
+                    dot += &edge(not_taken, "F");
+                }
+                Exit::Call { target, ret } => {
+                    dot += &edge(target, "call");
+                    dot += &edge(ret, "ret");
+                }
+                Exit::Indirect { not_taken } => {
+                    if let Some(to) = not_taken {
+                        dot += &edge(to, "F");
+                    }
+                }
+                Exit::Halt | Exit::Invalid => {}
+            }
+        }
+        dot += "}\n";
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::parse_program;
+
+    #[test]
+    fn branches_and_calls() {
+        // IN [24]; JF [24], #17; push 12; call 18; OUT #1; HLT; data; HLT;
+        // ARB #0; JT #1, rb+0; data
+        let program = parse_program(
+            "3,24,1006,24,17,21101,0,12,0,1105,1,18,104,1,99,0,0,99,109,0,2105,1,0,0,0",
+        );
+        let cfg = build(&program);
+        let exits: Vec<(usize, Exit)> = cfg.blocks.values().map(|b| (b.start, b.exit)).collect();
+        assert_eq!(
+            exits,
+            vec![
+                (
+                    0,
+                    Exit::Branch {
+                        taken: 17,
+                        not_taken: 5
+                    }
+                ),
+                (
+                    5,
+                    Exit::Call {
+                        target: 18,
+                        ret: 12
+                    }
+                ),
+                (12, Exit::Halt),
+                (17, Exit::Halt),
+                (18, Exit::Indirect { not_taken: None }),
+            ]
+        );
+        assert_eq!(cfg.predecessors(18), vec![5]);
+        assert_eq!(cfg.block_containing(14).map(|b| b.start), Some(12));
+        assert_eq!(cfg.block_containing(15), None);
+    }
+
+    #[test]
+    fn overflowing_pushes_are_not_calls() {
+        // push i64::MAX + 1, or i64::MAX * 2, then jump to 0
+        for op in &[21101, 21102] {
+            let program = parse_program(&format!("{},9223372036854775807,2,0,1105,1,0", op));
+            let exits: Vec<Exit> = build(&program).blocks.values().map(|b| b.exit).collect();
+            assert_eq!(exits, vec![Exit::Jump(0)]);
+        }
+    }
+
+    #[test]
+    fn flags_self_modifying_writes() {
+        // Both instructions of the day 2 sample write into the first one
+        let cfg = build(&parse_program("1,9,10,3,2,3,11,0,99,30,40,50"));
+        assert_eq!(
+            cfg.code_writes,
+            vec![
+                CodeWrite { ip: 0, address: 3 },
+                CodeWrite { ip: 4, address: 0 }
+            ]
+        );
+        assert_eq!(cfg.blocks.len(), 1);
+
+        let dot = cfg.to_dot();
+        assert!(dot.contains("b0 [label=\"     0  ADD [9], [10], [3]\\l"));
+        assert!(dot.contains("style=filled fillcolor=orange"));
+    }
+}
@@ -0,0 +1,382 @@
+// Ahead-of-time compilation of a program into closures. A linear sweep splits
+// the program into basic blocks, and every instruction in a block becomes a
+// closure with its operands already decoded. Running a block is then a chain
+// of closure calls with no decoding at all.
+//
+// Compiling assumes the code doesn't change. Any write into a compiled
+// instruction, including patching a program before running it, marks that
+// instruction dirty and from then on it's executed by the interpreter
+// straight from memory. So are jumps to addresses that weren't compiled.
+//
+// Compiled code bypasses tracing, history and profiling.
+
+use super::disasm::{disassemble, Entry};
+use super::io::{InputSource, OutputSink};
+use super::word::Word;
+use super::{get_computer, small, Fault, Instruction, IntCodeComputer, IntcodeError};
+use super::{Parameter, Signal};
+use std::collections::{BTreeSet, VecDeque};
+
+enum Flow {
+    Next,
+    Wrote(usize),
+    Jump(usize),
+    Output,
+    NeedsInput,
+    Halt,
+}
+
+type Op = Box<dyn Fn(&mut IntCodeComputer) -> Result<Flow, Fault> + Send + Sync>;
+
+struct Step {
+    ip: usize,
+    next: usize,
+    opcode: i64,
+    // One past the last step of this step's block
+    block_end: usize,
+    run: Op,
+}
+
+pub struct CompiledProgram {
+    program: Vec<i64>,
+    steps: Vec<Step>,
+    // Step starting at each address
+    entry: Vec<Option<usize>>,
+    // Step whose words include each address
+    owner: Vec<Option<usize>>,
+}
+
+fn write_op(
+    a: Parameter,
+    b: Parameter,
+    dst: Parameter,
+    f: impl Fn(i64, i64) -> Option<i64> + Send + Sync + 'static,
+) -> Op {
+    Box::new(move |c| {
+        let value = f(c.unwrap_value(a)?, c.unwrap_value(b)?).ok_or(Fault::Overflow)?;
+        Ok(Flow::Wrote(c.store_val(dst, value)?))
+    })
+}
+
+fn jump_op(cond: Parameter, target: Parameter, if_zero: bool) -> Op {
+    Box::new(move |c| {
+        if c.unwrap_value(cond)?.is_zero() != if_zero {
+            return Ok(Flow::Next);
+        }
+        let target = small(&c.unwrap_value(target)?)?;
+        Ok(Flow::Jump(c.address(target)?))
+    })
+}
+
+fn compile_instruction(inst: Instruction) -> Op {
+    match inst {
+        Instruction::Add((a, b, dst)) => write_op(a, b, dst, i64::checked_add),
+        Instruction::Mul((a, b, dst)) => write_op(a, b, dst, i64::checked_mul),
+        Instruction::LessThan((a, b, dst)) => write_op(a, b, dst, |a, b| Some((a < b) as i64)),
+        Instruction::Equals((a, b, dst)) => write_op(a, b, dst, |a, b| Some((a == b) as i64)),
+        Instruction::Input(dst) => Box::new(move |c| match c.input.pop_front() {
+            Some(value) => Ok(Flow::Wrote(c.store_val(dst, value)?)),
+            None => Ok(Flow::NeedsInput),
+        }),
+        Instruction::Output(src) => Box::new(move |c| {
+            c.emit_output(src)?;
+            Ok(Flow::Output)
+        }),
+        Instruction::JumpIfTrue((cond, target)) => jump_op(cond, target, false),
+        Instruction::JumpIfFalse((cond, target)) => jump_op(cond, target, true),
+        Instruction::RelativeBaseOffset(offset) => Box::new(move |c| {
+            let offset = small(&c.unwrap_value(offset)?)?;
+            c.relative_base_offset = c
+                .relative_base_offset
+                .checked_add(offset)
+                .ok_or(Fault::Overflow)?;
+            Ok(Flow::Next)
+        }),
+        Instruction::Halt => Box::new(|_| Ok(Flow::Halt)),
+    }
+}
+
+pub fn compile(program: &[i64]) -> CompiledProgram {
+    let code: Vec<(usize, Instruction)> = disassemble(program)
+        .into_iter()
+        .filter_map(|line| match line.entry {
+            Entry::Code(inst) => Some((line.address, inst)),
+            Entry::Data(_) => None,
+        })
+        .collect();
+
+    // Blocks start at jump targets and after anything that ends one
+    let mut leaders = BTreeSet::new();
+    for (ip, inst) in &code {
+        match inst {
+            Instruction::JumpIfTrue((_, target)) | Instruction::JumpIfFalse((_, target)) => {
+                match target {
+                    Parameter::Immediate(target) if *target >= 0 => {
+                        leaders.insert(*target as usize);
+                    }
+                    _ => {}
+                }
+                leaders.insert(ip + inst.size());
+            }
+            Instruction::Halt => {
+                leaders.insert(ip + 1);
+            }
+            _ => {}
+        }
+    }
+
+    let mut steps: Vec<Step> = Vec::with_capacity(code.len());
+    let mut entry = vec![None; program.len()];
+    let mut owner = vec![None; program.len()];
+    let mut block_start = 0;
+    for (ip, inst) in code {
+        let next = ip + inst.size();
+        // Data between two instructions also splits blocks
+        let follows = steps.last().is_some_and(|prev| prev.next == ip);
+        if !follows || leaders.contains(&ip) {
+            let end = steps.len();
+            for step in &mut steps[block_start..] {
+                step.block_end = end;
+            }
+            block_start = end;
+        }
+        entry[ip] = Some(steps.len());
+        owner[ip..next].fill(Some(steps.len()));
+        steps.push(Step {
+            ip,
+            next,
+            opcode: program[ip],
+            block_end: 0,
+            run: compile_instruction(inst),
+        });
+    }
+    let end = steps.len();
+    for step in &mut steps[block_start..] {
+        step.block_end = end;
+    }
+
+    CompiledProgram {
+        program: program.to_vec(),
+        steps,
+        entry,
+        owner,
+    }
+}
+
+impl CompiledProgram {
+    // Address ranges of the basic blocks
+    pub fn blocks(&self) -> Vec<(usize, usize)> {
+        let mut blocks = Vec::new();
+        let mut i = 0;
+        while i < self.steps.len() {
+            let last = &self.steps[self.steps[i].block_end - 1];
+            blocks.push((self.steps[i].ip, last.next));
+            i = self.steps[i].block_end;
+        }
+        blocks
+    }
+
+    // A fresh machine running this program. Machines share the compiled code,
+    // so a brute force search only compiles once.
+    pub fn machine(&self, input: Vec<i64>) -> CompiledMachine<'_> {
+        CompiledMachine {
+            program: self,
+            computer: get_computer(&self.program, input),
+            dirty: vec![false; self.steps.len()],
+        }
+    }
+}
+
+pub struct CompiledMachine<'p> {
+    program: &'p CompiledProgram,
+    computer: IntCodeComputer,
+    // Steps overwritten since compiling
+    dirty: Vec<bool>,
+}
+
+impl<'p> CompiledMachine<'p> {
+    pub fn computer(&self) -> &IntCodeComputer {
+        &self.computer
+    }
+
+    pub fn feed_input(&mut self, inp: i64) {
+        self.computer.feed_input(inp);
+    }
+
+    pub fn get_output(&mut self) -> Option<i64> {
+        self.computer.get_output()
+    }
+
+    pub fn get_value_at_pos(&self, i: usize) -> i64 {
+        self.computer.get_value_at_pos(i)
+    }
+
+    pub fn store_value_at_pos(&mut self, i: usize, value: i64) {
+        self.computer.store_value_at_pos(i, value);
+        self.mark(i);
+    }
+
+    fn mark(&mut self, addr: usize) {
+        if let Some(&Some(step)) = self.program.owner.get(addr) {
+            self.dirty[step] = true;
+        }
+    }
+
+    // Runs a single instruction from memory
+    fn interpret(&mut self) -> Result<Signal, IntcodeError> {
+        let c = &self.computer;
+        let target = match c.instruction_at(c.ip) {
+            Ok(Instruction::Add((_, _, dst)))
+            | Ok(Instruction::Mul((_, _, dst)))
+            | Ok(Instruction::LessThan((_, _, dst)))
+            | Ok(Instruction::Equals((_, _, dst)))
+            | Ok(Instruction::Input(dst)) => match dst {
+                Parameter::Position(addr) => c.address(addr).ok(),
+                Parameter::Relative(offset) => c.relative_address(&offset).ok(),
+                Parameter::Immediate(_) => None,
+            },
+            _ => None,
+        };
+        let signal = self.computer.tick()?;
+        if let Some(addr) = target {
+            self.mark(addr);
+        }
+        Ok(signal)
+    }
+
+    // Same contract as `IntCodeComputer::run`
+    pub fn run(&mut self) -> Result<Signal, IntcodeError> {
+        let program = self.program;
+        'blocks: loop {
+            let start = match program.entry.get(self.computer.ip) {
+                Some(&Some(start)) => start,
+                _ => match self.interpret()? {
+                    Signal::None => continue,
+                    signal => return Ok(signal),
+                },
+            };
+            let end = program.steps[start].block_end;
+            for (i, step) in program.steps[start..end].iter().enumerate() {
+                if self.dirty[start + i] {
+                    self.computer.ip = step.ip;
+                    match self.interpret()? {
+                        Signal::None => continue 'blocks,
+                        signal => return Ok(signal),
+                    }
+                }
+                let flow = match (step.run)(&mut self.computer) {
+                    Ok(flow) => flow,
+                    Err(fault) => {
+                        self.computer.ip = step.ip;
+                        return Err(fault.at(step.ip, step.opcode));
+                    }
+                };
+                match flow {
+                    Flow::Next => {}
+                    Flow::Wrote(addr) => self.mark(addr),
+                    Flow::Jump(target) => {
+                        self.computer.ip = target;
+                        continue 'blocks;
+                    }
+                    Flow::Output => {
+                        self.computer.ip = step.next;
+                        return Ok(Signal::ProducedOutput);
+                    }
+                    Flow::NeedsInput => {
+                        self.computer.ip = step.ip;
+                        return Ok(Signal::NeedsInput);
+                    }
+                    Flow::Halt => {
+                        self.computer.ip = step.ip;
+                        return Ok(Signal::Halt);
+                    }
+                }
+            }
+            self.computer.ip = program.steps[end - 1].next;
+        }
+    }
+
+    // Same contract as `IntCodeComputer::run_with_io`
+    pub fn run_with_io(
+        &mut self,
+        input: &mut impl InputSource,
+        output: &mut impl OutputSink,
+    ) -> Result<Signal, IntcodeError> {
+        loop {
+            match self.run()? {
+                Signal::NeedsInput => match input.next_input() {
+                    Some(value) => self.feed_input(value),
+                    None => return Ok(Signal::NeedsInput),
+                },
+                Signal::ProducedOutput => output.emit(self.get_output().unwrap()),
+                signal => return Ok(signal),
+            }
+        }
+    }
+
+    // Runs until the program halts or blocks on input, collecting every output
+    pub fn run_till_halt(&mut self) -> Result<Vec<i64>, IntcodeError> {
+        let mut outputs = Vec::new();
+        self.run_with_io(&mut VecDeque::new(), &mut outputs)?;
+        Ok(outputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::parse_program;
+
+    fn both(program: &str, input: Vec<i64>) -> (Vec<i64>, Vec<i64>) {
+        let program = parse_program(program);
+        let compiled = compile(&program).machine(input.clone()).run_till_halt();
+        let interpreted = get_computer(&program, input).run_till_halt();
+        (compiled.unwrap(), interpreted.unwrap())
+    }
+
+    #[test]
+    fn matches_interpreter() {
+        let quine = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+        let (compiled, interpreted) = both(quine, vec![]);
+        assert_eq!(compiled, interpreted);
+
+        let compare_to_8 = "3,9,8,9,10,9,4,9,99,-1,8";
+        for input in 7..10 {
+            let (compiled, interpreted) = both(compare_to_8, vec![input]);
+            assert_eq!(compiled, interpreted);
+        }
+    }
+
+    #[test]
+    fn splits_basic_blocks() {
+        // ADD; JT #1, #9; OUT; HLT; OUT; HLT
+        let compiled = compile(&parse_program("1101,1,2,0,1105,1,9,4,0,104,7,99"));
+        assert_eq!(compiled.blocks(), vec![(0, 7), (7, 9), (9, 12)]);
+    }
+
+    #[test]
+    fn overwritten_code_is_interpreted() {
+        // Outputs 1, 2, 3 by incrementing the operand of its own OUT
+        let (compiled, _) = both("104,1,1001,1,1,1,1001,14,-1,14,1005,14,0,99,3", vec![]);
+        assert_eq!(compiled, vec![1, 2, 3]);
+
+        let program = compile(&parse_program("1,9,10,3,2,3,11,0,99,30,40,50"));
+        let mut machine = program.machine(vec![]);
+        machine.store_value_at_pos(1, 10);
+        machine.store_value_at_pos(2, 9);
+        machine.run_till_halt().unwrap();
+        assert_eq!(machine.get_value_at_pos(0), 3500);
+    }
+
+    #[test]
+    fn resumes_after_needs_input() {
+        let compiled = compile(&parse_program("3,9,1001,9,1,9,4,9,99,0"));
+        let mut machine = compiled.machine(vec![]);
+        assert_eq!(machine.run(), Ok(Signal::NeedsInput));
+        machine.feed_input(41);
+        assert_eq!(machine.run_till_halt(), Ok(vec![42]));
+        // Left on the HLT, and halting again
+        assert_eq!(machine.computer().ip(), 8);
+        assert_eq!(machine.run(), Ok(Signal::Halt));
+    }
+}
@@ -0,0 +1,343 @@
+use super::watch::{Access, Hit};
+use super::{IntCodeComputer, IntcodeError, Signal};
+use std::collections::BTreeSet;
+use std::fmt;
+
+// Longest memory dump `mem` prints
+const MAX_DUMP: usize = 4096;
+
+pub enum Command {
+    Step(usize),
+    Back(usize),
+    BackTo(usize),
+    Continue,
+    Break(usize),
+    Watch(usize, Access),
+    Mem(usize, usize),
+    Regs,
+    Input(i64),
+    Help,
+    Quit,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Command, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let arg = |i: usize| -> Result<i64, String> {
+            let word = words
+                .get(i)
+                .ok_or_else(|| format!("`{}` is missing an argument", words[0]))?;
+            word.parse::<i64>()
+                .map_err(|_| format!("`{}` is not a number", word))
+        };
+        let addr = |i: usize| -> Result<usize, String> {
+            let n = arg(i)?;
+            if n < 0 {
+                return Err(format!("`{}` is not an address", n));
+            }
+            Ok(n as usize)
+        };
+
+        match words.first().copied() {
+            Some("s") | Some("step") if words.len() == 1 => Ok(Command::Step(1)),
+            Some("s") | Some("step") => Ok(Command::Step(addr(1)?)),
+            Some("back") if words.len() == 1 => Ok(Command::Back(1)),
+            Some("back") => Ok(Command::Back(addr(1)?)),
+            Some("backto") => Ok(Command::BackTo(addr(1)?)),
+            Some("c") | Some("continue") => Ok(Command::Continue),
+            Some("b") | Some("break") => Ok(Command::Break(addr(1)?)),
+            Some("w") | Some("watch") => {
+                let access = match words.get(2).copied() {
+                    None | Some("w") => Access::Write,
+                    Some("r") => Access::Read,
+                    Some("rw") => Access::ReadWrite,
+                    Some(other) => return Err(format!("`{}` is not r, w or rw", other)),
+                };
+                Ok(Command::Watch(addr(1)?, access))
+            }
+            Some("m") | Some("mem") => match addr(2)? {
+                len if len > MAX_DUMP => Err(format!("can dump at most {} words", MAX_DUMP)),
+                len => Ok(Command::Mem(addr(1)?, len)),
+            },
+            Some("r") | Some("regs") => Ok(Command::Regs),
+            Some("i") | Some("input") => Ok(Command::Input(arg(1)?)),
+            Some("h") | Some("help") => Ok(Command::Help),
+            Some("q") | Some("quit") => Ok(Command::Quit),
+            Some(other) => Err(format!("unknown command `{}`, try `help`", other)),
+            None => Err("empty command".to_string()),
+        }
+    }
+}
+
+pub const HELP: &str = "\
+step [n]             execute n instructions (default 1)
+back [n]             undo n instructions (default 1)
+backto <addr>        rewind to the last instruction that wrote a memory cell
+continue             run until a breakpoint, watchpoint, input request or halt
+break <addr>         toggle a breakpoint on an instruction address
+watch <addr> [r|rw]  toggle a watchpoint on writes to a memory cell, or on
+                     reads, or on both
+mem <start> <len>    dump memory
+regs                 show ip, relative base and queued input
+input <n>            queue an input value
+quit";
+
+#[derive(Debug, PartialEq)]
+pub enum Stop {
+    Stepped,
+    Breakpoint(usize),
+    Watchpoint(Vec<Hit>),
+    NeedsInput,
+    Halt,
+    Error(IntcodeError),
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stop::Stepped => Ok(()),
+            Stop::Breakpoint(ip) => write!(f, "breakpoint at {}", ip),
+            Stop::Watchpoint(hits) => {
+                let hits: Vec<String> = hits
+                    .iter()
+                    .map(|hit| format!("watchpoint: {}", hit))
+                    .collect();
+                write!(f, "{}", hits.join("\n"))
+            }
+            Stop::NeedsInput => write!(f, "waiting for input, use `input <n>`"),
+            Stop::Halt => write!(f, "program halted"),
+            Stop::Error(e) => write!(f, "error: {}", e),
+        }
+    }
+}
+
+pub struct Debugger {
+    computer: IntCodeComputer,
+    breakpoints: BTreeSet<usize>,
+    watches: BTreeSet<usize>,
+    outputs: Vec<i64>,
+    paused_at_break: bool,
+}
+
+impl Debugger {
+    pub fn new(computer: IntCodeComputer) -> Self {
+        Debugger {
+            computer,
+            breakpoints: BTreeSet::new(),
+            watches: BTreeSet::new(),
+            outputs: Vec::new(),
+            paused_at_break: false,
+        }
+    }
+
+    pub fn computer(&self) -> &IntCodeComputer {
+        &self.computer
+    }
+
+    // Returns whether the breakpoint is now set
+    pub fn toggle_breakpoint(&mut self, addr: usize) -> bool {
+        self.breakpoints.insert(addr) || !self.breakpoints.remove(&addr)
+    }
+
+    pub fn toggle_watch(&mut self, addr: usize, access: Access) -> bool {
+        if self.watches.remove(&addr) {
+            self.computer.unwatch(addr);
+            return false;
+        }
+        self.watches.insert(addr);
+        self.computer.watch(addr, access);
+        true
+    }
+
+    pub fn take_outputs(&mut self) -> Vec<i64> {
+        std::mem::take(&mut self.outputs)
+    }
+
+    pub fn step(&mut self) -> Stop {
+        self.paused_at_break = false;
+        let signal = match self.computer.tick() {
+            Ok(signal) => signal,
+            Err(e) => return Stop::Error(e),
+        };
+        match signal {
+            Signal::NeedsInput => return Stop::NeedsInput,
+            Signal::Halt => return Stop::Halt,
+            Signal::ProducedOutput => self.outputs.extend(self.computer.get_output()),
+            // Hits are taken below, straight after the instruction making them
+            Signal::Watchpoint | Signal::None => {}
+            // Single steps have no budget to run out of
+            Signal::BudgetExhausted => {}
+        }
+        match self.computer.take_watch_hits() {
+            hits if hits.is_empty() => Stop::Stepped,
+            hits => Stop::Watchpoint(hits),
+        }
+    }
+
+    // Breakpoints are checked before an instruction runs, except for the one
+    // we are resuming from
+    pub fn cont(&mut self) -> Stop {
+        let mut resuming = self.paused_at_break;
+        loop {
+            let ip = self.computer.ip();
+            if !resuming && self.breakpoints.contains(&ip) {
+                self.paused_at_break = true;
+                return Stop::Breakpoint(ip);
+            }
+            resuming = false;
+            match self.step() {
+                Stop::Stepped => {}
+                stop => return stop,
+            }
+        }
+    }
+
+    // Describes the instruction about to execute
+    pub fn current(&self) -> String {
+        let ip = self.computer.ip();
+        match self.computer.instruction_at(ip) {
+            Ok(inst) => format!("{:>6}  {}", ip, inst),
+            Err(e) => format!("{:>6}  ?? ({})", ip, e),
+        }
+    }
+
+    pub fn execute(&mut self, command: Command) -> String {
+        match command {
+            Command::Step(n) => {
+                let mut stop = Stop::Stepped;
+                for _ in 0..n {
+                    stop = self.step();
+                    if stop != Stop::Stepped {
+                        break;
+                    }
+                }
+                self.report(stop)
+            }
+            Command::Back(n) => {
+                let undone = self.computer.step_back_n(n);
+                self.resync();
+                format!("stepped back {} instructions\n{}", undone, self.current())
+            }
+            Command::BackTo(addr) => match self.computer.run_back_to_write(addr) {
+                Some(_) => {
+                    self.resync();
+                    format!("last write to [{}]\n{}", addr, self.current())
+                }
+                None => format!("no recorded write to [{}]", addr),
+            },
+            Command::Continue => {
+                let stop = self.cont();
+                self.report(stop)
+            }
+            Command::Break(addr) => match self.toggle_breakpoint(addr) {
+                true => format!("breakpoint set at {}", addr),
+                false => format!("breakpoint at {} removed", addr),
+            },
+            Command::Watch(addr, access) => match self.toggle_watch(addr, access) {
+                true => format!("watching [{}]", addr),
+                false => format!("no longer watching [{}]", addr),
+            },
+            Command::Mem(start, len) => (start..start + len)
+                .step_by(8)
+                .map(|row| {
+                    let words: Vec<String> = (row..(row + 8).min(start + len))
+                        .map(|i| format!("{:>8}", self.computer.get_value_at_pos(i)))
+                        .collect();
+                    format!("{:>6}: {}", row, words.join(""))
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Command::Regs => format!(
+                "ip={} rb={} input={:?}",
+                self.computer.ip(),
+                self.computer.relative_base(),
+                self.computer.pending_input()
+            ),
+            Command::Input(n) => {
+                self.computer.feed_input(n);
+                format!("queued input {}", n)
+            }
+            Command::Help => HELP.to_string(),
+            Command::Quit => String::new(),
+        }
+    }
+
+    // After rewinding, a breakpoint at the restored ip stops `continue` again
+    fn resync(&mut self) {
+        self.paused_at_break = false;
+    }
+
+    fn report(&mut self, stop: Stop) -> String {
+        let mut lines: Vec<String> = self
+            .take_outputs()
+            .iter()
+            .map(|out| format!("output: {}", out))
+            .collect();
+        if stop != Stop::Stepped {
+            lines.push(stop.to_string());
+        }
+        if stop != Stop::Halt {
+            lines.push(self.current());
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{get_computer, parse_program};
+
+    fn debugger(program: &str) -> Debugger {
+        Debugger::new(get_computer(&parse_program(program), vec![]))
+    }
+
+    #[test]
+    fn stops_for_input_and_resumes() {
+        let mut dbg = debugger("3,9,1001,9,1,9,4,9,99,0");
+        assert_eq!(dbg.cont(), Stop::NeedsInput);
+        dbg.execute(Command::Input(41));
+        assert_eq!(dbg.cont(), Stop::Halt);
+        assert_eq!(dbg.take_outputs(), vec![42]);
+    }
+
+    fn hit(ip: usize, access: Access, value: i64) -> Stop {
+        Stop::Watchpoint(vec![Hit {
+            ip,
+            address: 9,
+            access,
+            value,
+        }])
+    }
+
+    #[test]
+    fn breakpoints_and_watches() {
+        let mut dbg = debugger("1101,1,2,9,1001,9,1,9,99,0");
+        dbg.toggle_breakpoint(8);
+        dbg.toggle_watch(9, Access::Write);
+        assert_eq!(dbg.cont(), hit(0, Access::Write, 3));
+        assert_eq!(dbg.cont(), hit(4, Access::Write, 4));
+        assert_eq!(dbg.cont(), Stop::Breakpoint(8));
+        assert_eq!(dbg.cont(), Stop::Halt);
+    }
+
+    #[test]
+    fn watches_fire_without_a_change() {
+        // Writes 0 over the 0 already in [9], then reads it
+        let mut dbg = debugger("1101,0,0,9,4,9,99,0,0,0");
+        dbg.execute(Command::parse("watch 9 rw").unwrap());
+        assert_eq!(dbg.cont(), hit(0, Access::Write, 0));
+        assert_eq!(dbg.cont(), hit(4, Access::Read, 0));
+        assert_eq!(dbg.cont(), Stop::Halt);
+    }
+
+    #[test]
+    fn memory_dumps_are_bounded() {
+        let mut dbg = debugger("1,2,3,4,5,6,7,8,9,10");
+        assert_eq!(
+            dbg.execute(Command::parse("mem 6 4").unwrap()),
+            "     6:        7       8       9      10"
+        );
+        assert!(Command::parse("mem 0 9223372036854775807").is_err());
+    }
+}
@@ -0,0 +1,733 @@
+// Lifts a control-flow graph to structured pseudo-code, one function per call
+// target plus `main` at address 0.
+//
+// Functions are assumed to follow the relative base convention: the caller
+// stores the return address at `rb+0` and jumps, the callee moves `rb` past
+// its frame on entry and moves it back right before jumping to `rb+0`. The
+// prologue, epilogue, return address push and jump are folded into calls and
+// returns. Relative slots are named by their offset from `rb` on entry to the
+// function, so `l1` is the same slot throughout a function however `rb`
+// moves inside it. Fixed addresses are `v<addr>`, or `mem[<addr>]` when they
+// hold code.
+//
+// Loops are found from back edges and the join point of an if/else is the
+// immediate post-dominator of the branch. Anything that doesn't fit falls
+// back to `goto`.
+
+use super::cfg::{self, Cfg, Exit};
+use super::{Instruction, Parameter};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+// Virtual node every returning or halting block flows into
+const EXIT: usize = usize::MAX;
+
+// Successors within a function, where a call continues at its return address
+fn local_successors(exit: &Exit) -> Vec<usize> {
+    match *exit {
+        Exit::Call { ret, .. } => vec![ret],
+        _ => exit.successors(),
+    }
+}
+
+struct Loop {
+    body: BTreeSet<usize>,
+    exit: Option<usize>,
+}
+
+struct Function<'a> {
+    cfg: &'a Cfg,
+    entry: usize,
+    blocks: BTreeSet<usize>,
+    // `rb` at the start of each block relative to its value on entry, when
+    // it can be tracked statically
+    frames: BTreeMap<usize, Option<i64>>,
+    ipdom: BTreeMap<usize, usize>,
+    loops: BTreeMap<usize, Loop>,
+}
+
+// Iterates `sets[n] = {n} ∪ ⋂ sets[m] for m in edges[n]` to a fixed point.
+// Forward over predecessors this gives dominators, backward over successors
+// post-dominators.
+fn dominators(
+    nodes: &BTreeSet<usize>,
+    edges: &BTreeMap<usize, Vec<usize>>,
+    roots: &BTreeSet<usize>,
+) -> BTreeMap<usize, BTreeSet<usize>> {
+    let mut all = nodes.clone();
+    all.insert(EXIT);
+    let mut sets: BTreeMap<usize, BTreeSet<usize>> =
+        nodes.iter().map(|&n| (n, all.clone())).collect();
+    for &root in roots {
+        sets.insert(root, [root].iter().copied().collect());
+    }
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &n in nodes.iter().filter(|n| !roots.contains(n)) {
+            let mut set = edges[&n]
+                .iter()
+                .map(|m| &sets[m])
+                .fold(None, |acc: Option<BTreeSet<usize>>, s| match acc {
+                    Some(acc) => Some(acc.intersection(s).copied().collect()),
+                    None => Some(s.clone()),
+                })
+                .unwrap_or_default();
+            set.insert(n);
+            if set != sets[&n] {
+                sets.insert(n, set);
+                changed = true;
+            }
+        }
+    }
+    sets
+}
+
+impl<'a> Function<'a> {
+    fn new(cfg: &'a Cfg, entry: usize) -> Function<'a> {
+        let mut blocks = BTreeSet::new();
+        let mut pending = vec![entry];
+        while let Some(b) = pending.pop() {
+            if cfg.blocks.contains_key(&b) && blocks.insert(b) {
+                pending.extend(local_successors(&cfg.blocks[&b].exit));
+            }
+        }
+        let successors: BTreeMap<usize, Vec<usize>> = blocks
+            .iter()
+            .map(|&b| {
+                let succ = local_successors(&cfg.blocks[&b].exit);
+                (b, succ.into_iter().filter(|s| blocks.contains(s)).collect())
+            })
+            .collect();
+        let mut predecessors: BTreeMap<usize, Vec<usize>> =
+            blocks.iter().map(|&b| (b, Vec::new())).collect();
+        for (&b, succ) in &successors {
+            for s in succ {
+                predecessors.get_mut(s).unwrap().push(b);
+            }
+        }
+
+        let mut function = Function {
+            cfg,
+            entry,
+            frames: BTreeMap::new(),
+            ipdom: BTreeMap::new(),
+            loops: BTreeMap::new(),
+            blocks,
+        };
+        function.track_frames(&successors);
+        function.find_joins(&successors);
+        function.find_loops(&successors, &predecessors);
+        function
+    }
+
+    fn track_frames(&mut self, successors: &BTreeMap<usize, Vec<usize>>) {
+        self.frames.insert(self.entry, Some(0));
+        let mut pending = vec![self.entry];
+        while let Some(b) = pending.pop() {
+            let block = match self.cfg.blocks.get(&b) {
+                Some(block) => block,
+                None => continue,
+            };
+            let frame = block
+                .instructions
+                .iter()
+                .fold(self.frames[&b], |frame, (_, inst)| advance(frame, inst));
+            for &s in &successors[&b] {
+                let merged = match self.frames.get(&s) {
+                    None => frame,
+                    Some(&known) if known == frame => continue,
+                    Some(_) => None,
+                };
+                if self.frames.insert(s, merged) != Some(merged) {
+                    pending.push(s);
+                }
+            }
+        }
+    }
+
+    fn find_joins(&mut self, successors: &BTreeMap<usize, Vec<usize>>) {
+        let mut edges = successors.clone();
+        for succ in edges.values_mut() {
+            if succ.is_empty() {
+                succ.push(EXIT);
+            }
+        }
+        edges.insert(EXIT, Vec::new());
+        let mut nodes = self.blocks.clone();
+        nodes.insert(EXIT);
+        let roots = [EXIT].iter().copied().collect();
+        let pdom = dominators(&nodes, &edges, &roots);
+        for &b in &self.blocks {
+            let strict = pdom[&b].len() - 1;
+            let ipdom = pdom[&b]
+                .iter()
+                .find(|&&p| p != b && p != EXIT && pdom[&p].len() == strict);
+            if let Some(&p) = ipdom {
+                self.ipdom.insert(b, p);
+            }
+        }
+    }
+
+    fn find_loops(
+        &mut self,
+        successors: &BTreeMap<usize, Vec<usize>>,
+        predecessors: &BTreeMap<usize, Vec<usize>>,
+    ) {
+        let roots = [self.entry].iter().copied().collect();
+        let dom = dominators(&self.blocks, predecessors, &roots);
+        for (&tail, succ) in successors {
+            for &header in succ.iter().filter(|h| dom[&tail].contains(h)) {
+                let body = &mut self
+                    .loops
+                    .entry(header)
+                    .or_insert_with(|| Loop {
+                        body: [header].iter().copied().collect(),
+                        exit: None,
+                    })
+                    .body;
+                let mut pending = vec![tail];
+                while let Some(b) = pending.pop() {
+                    if body.insert(b) {
+                        pending.extend(&predecessors[&b]);
+                    }
+                }
+            }
+        }
+        for (header, lp) in self.loops.iter_mut() {
+            let exits: BTreeSet<usize> = lp
+                .body
+                .iter()
+                .flat_map(|b| &successors[b])
+                .copied()
+                .filter(|s| !lp.body.contains(s))
+                .collect();
+            // Prefer the join point after the loop when there are several
+            lp.exit = match self.ipdom.get(header) {
+                Some(p) if exits.len() > 1 && !lp.body.contains(p) => Some(*p),
+                _ => exits.iter().next().copied(),
+            };
+        }
+    }
+
+    fn name(&self) -> String {
+        match self.entry {
+            0 => "main".to_string(),
+            entry => format!("f{}", entry),
+        }
+    }
+}
+
+struct Cond {
+    lhs: String,
+    op: &'static str,
+    rhs: String,
+}
+
+impl Cond {
+    fn negate(self) -> Cond {
+        let op = match self.op {
+            "<" => ">=",
+            ">=" => "<",
+            "==" => "!=",
+            _ => "==",
+        };
+        Cond { op, ..self }
+    }
+}
+
+impl std::fmt::Display for Cond {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.lhs, self.op, self.rhs)
+    }
+}
+
+fn immediate(param: &Parameter) -> Option<i64> {
+    match param {
+        Parameter::Immediate(value) => Some(*value),
+        _ => None,
+    }
+}
+
+struct Writer<'a> {
+    function: &'a Function<'a>,
+    code: &'a BTreeSet<usize>,
+    lines: Vec<(usize, String)>,
+    depth: usize,
+    starts: BTreeMap<usize, usize>,
+    gotos: BTreeSet<usize>,
+    emitted: BTreeSet<usize>,
+    // Header and exit of each enclosing loop
+    loops: Vec<(usize, Option<usize>)>,
+}
+
+impl<'a> Writer<'a> {
+    fn line(&mut self, text: impl Into<String>) {
+        self.lines.push((self.depth, text.into()));
+    }
+
+    fn operand(&self, param: &Parameter, frame: Option<i64>) -> String {
+        match *param {
+            Parameter::Immediate(value) => value.to_string(),
+            Parameter::Position(addr) if addr >= 0 && !self.code.contains(&(addr as usize)) => {
+                format!("v{}", addr)
+            }
+            Parameter::Position(addr) => format!("mem[{}]", addr),
+            Parameter::Relative(offset) => match frame.and_then(|f| f.checked_add(offset)) {
+                // `rb` starts at 0, so main's frame offsets are addresses
+                Some(addr) if self.function.entry == 0 => {
+                    self.operand(&Parameter::Position(addr), frame)
+                }
+                Some(slot) if slot >= 0 => format!("l{}", slot),
+                Some(slot) => format!("frame[{}]", slot),
+                None => format!("rb[{}]", offset),
+            },
+        }
+    }
+
+    fn sum(&self, a: &Parameter, b: &Parameter, frame: Option<i64>) -> String {
+        let (x, y) = (self.operand(a, frame), self.operand(b, frame));
+        match (immediate(a), immediate(b)) {
+            (Some(a), Some(b)) if a.checked_add(b).is_some() => (a + b).to_string(),
+            (Some(0), _) => y,
+            (_, Some(0)) => x,
+            (_, Some(b)) if b < 0 && b != i64::MIN => format!("{} - {}", x, -b),
+            _ => format!("{} + {}", x, y),
+        }
+    }
+
+    fn product(&self, a: &Parameter, b: &Parameter, frame: Option<i64>) -> String {
+        let (x, y) = (self.operand(a, frame), self.operand(b, frame));
+        match (immediate(a), immediate(b)) {
+            (Some(a), Some(b)) if a.checked_mul(b).is_some() => (a * b).to_string(),
+            (Some(0), _) | (_, Some(0)) => "0".to_string(),
+            (Some(1), _) => y,
+            (_, Some(1)) => x,
+            (Some(-1), _) => format!("-{}", y),
+            (_, Some(-1)) => format!("-{}", x),
+            _ => format!("{} * {}", x, y),
+        }
+    }
+
+    fn statement(&self, inst: &Instruction, frame: Option<i64>) -> String {
+        let op = |p: &Parameter| self.operand(p, frame);
+        match inst {
+            Instruction::Add((a, b, dst)) => format!("{} = {}", op(dst), self.sum(a, b, frame)),
+            Instruction::Mul((a, b, dst)) => {
+                format!("{} = {}", op(dst), self.product(a, b, frame))
+            }
+            Instruction::LessThan((a, b, dst)) => format!("{} = {} < {}", op(dst), op(a), op(b)),
+            Instruction::Equals((a, b, dst)) => format!("{} = {} == {}", op(dst), op(a), op(b)),
+            Instruction::Input(dst) => format!("{} = input()", op(dst)),
+            Instruction::Output(src) => format!("output({})", op(src)),
+            Instruction::RelativeBaseOffset(offset) => format!("rb += {}", op(offset)),
+            Instruction::JumpIfTrue((cond, target)) => {
+                format!("if {} != 0 {{ goto *{} }}", op(cond), op(target))
+            }
+            Instruction::JumpIfFalse((cond, target)) => {
+                format!("if {} == 0 {{ goto *{} }}", op(cond), op(target))
+            }
+            Instruction::Halt => "halt".to_string(),
+        }
+    }
+
+    // The condition under which the jump ending a block is taken. A
+    // comparison stored right before the jump is folded into it.
+    fn condition(&self, insts: &[(usize, Instruction)], frame: Option<i64>) -> Cond {
+        let (cond, jump_if) = match insts.last() {
+            Some((_, Instruction::JumpIfTrue((cond, _)))) => (cond, true),
+            Some((_, Instruction::JumpIfFalse((cond, _)))) => (cond, false),
+            _ => unreachable!("branches end in a jump"),
+        };
+        let overwrites = |a: &Parameter, b: &Parameter| a == cond || b == cond;
+        let compare = match insts.len().checked_sub(2).map(|i| &insts[i].1) {
+            Some(Instruction::LessThan((a, b, dst))) if dst == cond && !overwrites(a, b) => {
+                Some((a, "<", b))
+            }
+            Some(Instruction::Equals((a, b, dst))) if dst == cond && !overwrites(a, b) => {
+                Some((a, "==", b))
+            }
+            _ => None,
+        };
+        let taken = match compare {
+            Some((a, op, b)) => Cond {
+                lhs: self.operand(a, frame),
+                op,
+                rhs: self.operand(b, frame),
+            },
+            None => Cond {
+                lhs: self.operand(cond, frame),
+                op: "!=",
+                rhs: "0".to_string(),
+            },
+        };
+        match jump_if {
+            true => taken,
+            false => taken.negate(),
+        }
+    }
+
+    // `break` or `continue` when `b` leaves or restarts the innermost loop
+    fn loop_jump(&self, b: usize) -> Option<&'static str> {
+        let &(header, exit) = self.loops.last()?;
+        match b {
+            _ if b == header => Some("continue"),
+            _ if Some(b) == exit => Some("break"),
+            _ => None,
+        }
+    }
+
+    fn region(&mut self, mut b: usize, stop: Option<usize>) {
+        loop {
+            if Some(b) == stop {
+                return;
+            }
+            if let Some(jump) = self.loop_jump(b) {
+                self.line(jump);
+                return;
+            }
+            if self.emitted.contains(&b) || !self.function.blocks.contains(&b) {
+                self.gotos.insert(b);
+                self.line(format!("goto L{}", b));
+                return;
+            }
+            let next = match self.function.loops.get(&b) {
+                Some(lp) => {
+                    self.line("loop {");
+                    self.depth += 1;
+                    self.loops.push((b, lp.exit));
+                    if let Some(next) = self.block(b) {
+                        self.region(next, None);
+                    }
+                    self.loops.pop();
+                    if let Some((depth, line)) = self.lines.last_mut() {
+                        if *depth == self.depth && line == "continue" {
+                            line.clear();
+                        }
+                    }
+                    self.depth -= 1;
+                    self.line("}");
+                    lp.exit
+                }
+                None => self.block(b),
+            };
+            match next {
+                Some(next) => b = next,
+                None => return,
+            }
+        }
+    }
+
+    fn arm(&mut self, head: String, start: usize, join: Option<usize>) {
+        self.line(head);
+        self.depth += 1;
+        self.region(start, join);
+        self.depth -= 1;
+    }
+
+    // Emits a block and whatever hangs off its exit, returning where straight
+    // line flow carries on
+    fn block(&mut self, b: usize) -> Option<usize> {
+        self.emitted.insert(b);
+        self.starts.insert(b, self.lines.len());
+        let function = self.function;
+        let block = &function.cfg.blocks[&b];
+        let insts = &block.instructions;
+        let mut frame = function.frames[&b];
+
+        let (last_ip, last) = insts[insts.len() - 1];
+        let returns = match (block.exit, last) {
+            (Exit::Indirect { .. }, Instruction::JumpIfTrue((_, target)))
+            | (Exit::Indirect { .. }, Instruction::JumpIfFalse((_, target))) => {
+                // `rb` at the jump, given the epilogue right before it
+                let epilogue = match insts.len().checked_sub(2).map(|i| insts[i].1) {
+                    Some(Instruction::RelativeBaseOffset(Parameter::Immediate(off))) => off,
+                    _ => 0,
+                };
+                let at_jump = insts
+                    .iter()
+                    .fold(frame, |frame, (_, inst)| advance(frame, inst));
+                match target {
+                    Parameter::Relative(offset)
+                        if at_jump.and_then(|f| f.checked_add(offset)) == Some(0) =>
+                    {
+                        Some(epilogue != 0 && function.entry != 0)
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        let body = match block.exit {
+            Exit::Fallthrough(_) | Exit::Invalid => insts.len(),
+            Exit::Call { .. } => insts.len().saturating_sub(2),
+            _ if returns == Some(true) => insts.len() - 2,
+            _ => insts.len() - 1,
+        };
+
+        for (i, (_, inst)) in insts[..body].iter().enumerate() {
+            let prologue = i == 0 && b == function.entry && function.entry != 0;
+            match inst {
+                Instruction::RelativeBaseOffset(Parameter::Immediate(_)) if prologue => {}
+                _ => {
+                    let statement = self.statement(inst, frame);
+                    self.line(statement);
+                }
+            }
+            frame = advance(frame, inst);
+        }
+        for (_, inst) in &insts[body..] {
+            frame = advance(frame, inst);
+        }
+
+        match block.exit {
+            Exit::Fallthrough(next) | Exit::Jump(next) => Some(next),
+            Exit::Call { target, ret } => {
+                let call = match frame {
+                    Some(0) if function.entry != 0 => format!("f{}()", target),
+                    Some(_) => {
+                        let base = self.operand(&Parameter::Relative(0), frame);
+                        format!("f{}()  // frame at {}", target, base)
+                    }
+                    None => format!("f{}()  // frame unknown", target),
+                };
+                self.line(call);
+                Some(ret)
+            }
+            Exit::Halt => {
+                self.line("halt");
+                None
+            }
+            Exit::Invalid => {
+                self.line(format!("// invalid code at {}", block.end));
+                None
+            }
+            Exit::Indirect { not_taken } => {
+                let jump = match (returns, last) {
+                    (Some(_), _) => "return".to_string(),
+                    (None, Instruction::JumpIfTrue((_, target)))
+                    | (None, Instruction::JumpIfFalse((_, target))) => {
+                        format!("goto *{}", self.operand(&target, frame))
+                    }
+                    _ => unreachable!("indirect exits end in a jump at {}", last_ip),
+                };
+                match not_taken {
+                    Some(next) => {
+                        let cond = self.condition(insts, frame);
+                        self.line(format!("if {} {{ {} }}", cond, jump));
+                        Some(next)
+                    }
+                    None => {
+                        self.line(jump);
+                        None
+                    }
+                }
+            }
+            Exit::Branch { taken, not_taken } => {
+                let cond = self.condition(insts, frame);
+                let join = function
+                    .ipdom
+                    .get(&b)
+                    .copied()
+                    .filter(|j| match self.loops.last() {
+                        Some((header, _)) => function.loops[header].body.contains(j),
+                        None => true,
+                    });
+                if let Some(jump) = self.loop_jump(taken) {
+                    self.line(format!("if {} {{ {} }}", cond, jump));
+                    return Some(not_taken);
+                }
+                if let Some(jump) = self.loop_jump(not_taken) {
+                    self.line(format!("if {} {{ {} }}", cond.negate(), jump));
+                    return Some(taken);
+                }
+                if Some(taken) == join {
+                    self.arm(format!("if {} {{", cond.negate()), not_taken, join);
+                } else if Some(not_taken) == join {
+                    self.arm(format!("if {} {{", cond), taken, join);
+                } else {
+                    let head = self.lines.len();
+                    let negated = self.condition(insts, frame).negate();
+                    self.arm(format!("if {} {{", cond), taken, join);
+                    let middle = self.lines.len();
+                    self.arm("} else {".to_string(), not_taken, join);
+                    // An arm may be nothing but a jump to the join point
+                    if self.lines.len() == middle + 1 {
+                        self.lines[middle].1.clear();
+                    } else if middle == head + 1 {
+                        self.lines[head].1 = format!("if {} {{", negated);
+                        self.lines[middle].1.clear();
+                    }
+                }
+                self.line("}");
+                join
+            }
+        }
+    }
+
+    fn finish(self) -> String {
+        let name = self.function.name();
+        let mut text = format!("fn {}() {{\n", name);
+        let labels: BTreeMap<usize, usize> = self
+            .gotos
+            .iter()
+            .filter_map(|b| Some((*self.starts.get(b)?, *b)))
+            .collect();
+        for (i, (depth, line)) in self.lines.iter().enumerate() {
+            if let Some(b) = labels.get(&i) {
+                writeln!(text, "{}L{}:", "    ".repeat(*depth), b).unwrap();
+            }
+            // Lines dropped after the fact are left empty
+            if !line.is_empty() {
+                writeln!(text, "{}{}", "    ".repeat(depth + 1), line).unwrap();
+            }
+        }
+        text + "}\n"
+    }
+}
+
+// The frame offset after `inst`, lost once `rb` moves by a computed amount
+// or past what an i64 can hold
+fn advance(frame: Option<i64>, inst: &Instruction) -> Option<i64> {
+    match inst {
+        Instruction::RelativeBaseOffset(Parameter::Immediate(off)) => {
+            frame.and_then(|f| f.checked_add(*off))
+        }
+        Instruction::RelativeBaseOffset(_) => None,
+        _ => frame,
+    }
+}
+
+pub fn decompile(program: &[i64]) -> String {
+    let cfg = cfg::build(program);
+    let code: BTreeSet<usize> = cfg.blocks.values().flat_map(|b| b.start..b.end).collect();
+    let mut entries: BTreeSet<usize> = cfg
+        .blocks
+        .values()
+        .filter_map(|b| match b.exit {
+            Exit::Call { target, .. } => Some(target),
+            _ => None,
+        })
+        .collect();
+    entries.insert(0);
+
+    let mut text = Vec::new();
+    for entry in entries {
+        let function = Function::new(&cfg, entry);
+        let mut writer = Writer {
+            function: &function,
+            code: &code,
+            lines: Vec::new(),
+            depth: 0,
+            starts: BTreeMap::new(),
+            gotos: BTreeSet::new(),
+            emitted: BTreeSet::new(),
+            loops: Vec::new(),
+        };
+        // An empty program, or a call into data or past the end
+        if !cfg.blocks.contains_key(&entry) {
+            writer.line(format!("// invalid code at {}", entry));
+            text.push(writer.finish());
+            continue;
+        }
+        for write in &cfg.code_writes {
+            if let Some(block) = cfg.block_containing(write.ip) {
+                if function.blocks.contains(&block.start) {
+                    writer.line(format!(
+                        "// writes into code: mem[{}] at {}",
+                        write.address, write.ip
+                    ));
+                }
+            }
+        }
+        writer.region(entry, None);
+        text.push(writer.finish());
+    }
+    text.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::asm::assemble;
+    use crate::intcode::parse_program;
+
+    #[test]
+    fn calls_loops_and_branches() {
+        let program = assemble(
+            "
+                    ARB  #100
+            loop:   IN   rb+1
+                    JF   rb+1, #done
+                    ADD  #0, #ret, rb+0
+                    JT   #1, #step
+            ret:    OUT  rb+1
+                    JT   #1, #loop
+            done:   HLT
+            step:   ARB  #2
+                    LT   rb-1, #10, rb+0
+                    JT   rb+0, #small
+                    MUL  rb-1, #2, rb-1
+                    JT   #1, #end
+            small:  ADD  rb-1, #1, rb-1
+            end:    ARB  #-2
+                    JT   #1, rb+0
+            ",
+        )
+        .unwrap();
+        let expected = "\
+fn main() {
+    rb += 100
+    loop {
+        v101 = input()
+        if v101 == 0 { break }
+        f20()  // frame at v100
+        output(v101)
+    }
+    halt
+}
+
+fn f20() {
+    l2 = l1 < 10
+    if l1 < 10 {
+        l1 = l1 + 1
+    } else {
+        l1 = l1 * 2
+    }
+    return
+}
+";
+        assert_eq!(decompile(&program), expected);
+    }
+
+    #[test]
+    fn names_code_and_data() {
+        let expected = "\
+fn main() {
+    // writes into code: mem[3] at 0
+    // writes into code: mem[0] at 4
+    mem[3] = v9 + v10
+    mem[0] = mem[3] * v11
+    halt
+}
+";
+        let program = parse_program("1,9,10,3,2,3,11,0,99,30,40,50");
+        assert_eq!(decompile(&program), expected);
+    }
+
+    #[test]
+    fn stubs_code_that_does_not_decode() {
+        assert_eq!(decompile(&[]), "fn main() {\n    // invalid code at 0\n}\n");
+        // Calls past the end of the program
+        let program = parse_program("21101,0,7,0,1105,1,100");
+        assert!(decompile(&program).ends_with("fn f100() {\n    // invalid code at 100\n}\n"));
+    }
+
+    #[test]
+    fn frames_past_i64_are_unknown() {
+        let moved = decompile(&parse_program("109,9223372036854775807,109,1,99"));
+        assert!(moved.contains("rb += 1\n    halt"));
+        let slot = decompile(&parse_program("109,9223372036854775807,204,1,99"));
+        assert!(slot.contains("output(rb[1])"));
+    }
+}
@@ -0,0 +1,76 @@
+use super::{get_instruction, Instruction};
+use std::fmt;
+
+pub enum Entry {
+    Code(Instruction),
+    Data(i64),
+}
+
+pub struct Line {
+    pub address: usize,
+    pub words: Vec<i64>,
+    pub entry: Entry,
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let words: Vec<String> = self.words.iter().map(|w| w.to_string()).collect();
+        write!(f, "{:>6}  {:<28}", self.address, words.join(","))?;
+        match &self.entry {
+            Entry::Code(inst) => write!(f, "{}", inst),
+            Entry::Data(word) => write!(f, "DATA {}", word),
+        }
+    }
+}
+
+// Linear sweep over the program. Anything that doesn't decode into a complete
+// instruction is emitted one word at a time as data.
+pub fn disassemble(program: &[i64]) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut address = 0;
+    while address < program.len() {
+        let line = match get_instruction(program, address) {
+            Ok(inst) if address + inst.size() <= program.len() => Line {
+                address,
+                words: program[address..address + inst.size()].to_vec(),
+                entry: Entry::Code(inst),
+            },
+            _ => Line {
+                address,
+                words: vec![program[address]],
+                entry: Entry::Data(program[address]),
+            },
+        };
+        address += line.words.len();
+        lines.push(line);
+    }
+    lines
+}
+
+pub fn listing(program: &[i64]) -> String {
+    disassemble(program)
+        .iter()
+        .map(|line| format!("{}\n", line.to_string().trim_end()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::parse_program;
+
+    #[test]
+    fn annotated_listing() {
+        let program = parse_program("1002,4,3,4,33,109,-2,204,1,99,7,-1");
+        let expected = [
+            "     0  1002,4,3,4                  MUL [4], #3, [4]",
+            "     4  33                          DATA 33",
+            "     5  109,-2                      ARB #-2",
+            "     7  204,1                       OUT rb+1",
+            "     9  99                          HLT",
+            "    10  7                           DATA 7",
+            "    11  -1                          DATA -1",
+        ];
+        assert_eq!(listing(&program), expected.join("\n") + "\n");
+    }
+}
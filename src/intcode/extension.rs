@@ -0,0 +1,352 @@
+// Custom opcodes. An `InstructionSet` maps opcodes the VM doesn't know to a
+// name, the role of each parameter and a closure that executes it:
+//
+//     let mut set: InstructionSet = InstructionSet::new();
+//     set.add(10, "DIV", &[Role::Read, Role::Read, Role::Write], |ops| {
+//         match ops.get(1)?.to_i64() {
+//             Some(0) => Err("division by zero".to_string()),
+//             _ => ...,
+//         }
+//     })?;
+//     computer.set_instruction_set(Arc::new(set));
+//
+// Parameter modes work as for the built-in opcodes. The closure sees the
+// values of its read and jump parameters, and through `Operands` can set its
+// write parameters, take the jump, or produce an output. An error from the
+// closure stops the program with `IntcodeError::Extension`, as does asking
+// `Operands` for a parameter the opcode doesn't have or can't write.
+//
+// The built-in opcodes always win, so an instruction set can only add to
+// them. Only the interpreter knows about the extensions: the disassembler,
+// assembler and other tools still see their words as data, traces and
+// watchpoints skip them, and the compiled backend has no instruction set, so
+// they fail there with `IntcodeError::UnknownOpcode`.
+
+use super::memory::Memory;
+use super::word::Word;
+use super::{get_parameter, small, Fault, IntCodeComputer, Parameter, Signal};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+// The modes of an instruction's parameters have to fit in an i64 with it
+const MAX_PARAMETERS: usize = 17;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Read,
+    Write,
+    // Read, and used as the target if the instruction jumps
+    Jump,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RegistryError {
+    // Built in, or not an opcode at all
+    Reserved(i64),
+    Duplicate(i64),
+    // An instruction can only jump to one place
+    BadJump(i64),
+    // More parameters than there are digits for their modes
+    TooManyParameters(i64),
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RegistryError::Reserved(opcode) => write!(f, "opcode {} is reserved", opcode),
+            RegistryError::Duplicate(opcode) => write!(f, "opcode {} added twice", opcode),
+            RegistryError::BadJump(opcode) => {
+                write!(f, "opcode {} has more than one jump parameter", opcode)
+            }
+            RegistryError::TooManyParameters(opcode) => write!(
+                f,
+                "opcode {} has more than {} parameters",
+                opcode, MAX_PARAMETERS
+            ),
+        }
+    }
+}
+
+impl Error for RegistryError {}
+
+pub struct Operands<W: Word> {
+    roles: Vec<Role>,
+    values: Vec<W>,
+    writes: Vec<Option<W>>,
+    jump: bool,
+    output: Option<W>,
+}
+
+impl<W: Word> Operands<W> {
+    // The value of a read or jump parameter, counting from 0. Write
+    // parameters read as zero.
+    pub fn get(&self, i: usize) -> Result<&W, String> {
+        self.values
+            .get(i)
+            .ok_or_else(|| format!("there is no parameter {}", i))
+    }
+
+    pub fn set(&mut self, i: usize, value: W) -> Result<(), String> {
+        if self.roles.get(i) != Some(&Role::Write) {
+            return Err(format!("parameter {} is not written", i));
+        }
+        self.writes[i] = Some(value);
+        Ok(())
+    }
+
+    pub fn jump(&mut self) {
+        self.jump = true;
+    }
+
+    pub fn output(&mut self, value: W) {
+        self.output = Some(value);
+    }
+}
+
+type Exec<W> = dyn Fn(&mut Operands<W>) -> Result<(), String> + Send + Sync;
+
+pub struct Extension<W: Word> {
+    pub name: &'static str,
+    pub roles: Vec<Role>,
+    exec: Box<Exec<W>>,
+}
+
+pub struct InstructionSet<W: Word = i64> {
+    opcodes: BTreeMap<i64, Extension<W>>,
+}
+
+impl<W: Word> Default for InstructionSet<W> {
+    fn default() -> Self {
+        InstructionSet {
+            opcodes: BTreeMap::new(),
+        }
+    }
+}
+
+impl<W: Word> InstructionSet<W> {
+    pub fn new() -> Self {
+        InstructionSet::default()
+    }
+
+    pub fn add(
+        &mut self,
+        opcode: i64,
+        name: &'static str,
+        roles: &[Role],
+        exec: impl Fn(&mut Operands<W>) -> Result<(), String> + Send + Sync + 'static,
+    ) -> Result<&mut Self, RegistryError> {
+        // The two lowest digits are the opcode, the rest are parameter modes
+        if !(1..=98).contains(&opcode) || (1..=9).contains(&opcode) {
+            return Err(RegistryError::Reserved(opcode));
+        }
+        if roles.len() > MAX_PARAMETERS {
+            return Err(RegistryError::TooManyParameters(opcode));
+        }
+        if roles.iter().filter(|&&r| r == Role::Jump).count() > 1 {
+            return Err(RegistryError::BadJump(opcode));
+        }
+        if self.opcodes.contains_key(&opcode) {
+            return Err(RegistryError::Duplicate(opcode));
+        }
+        let extension = Extension {
+            name,
+            roles: roles.to_vec(),
+            exec: Box::new(exec),
+        };
+        self.opcodes.insert(opcode, extension);
+        Ok(self)
+    }
+
+    pub fn get(&self, opcode: i64) -> Option<&Extension<W>> {
+        self.opcodes.get(&opcode)
+    }
+}
+
+impl<M: Memory> IntCodeComputer<M> {
+    pub fn set_instruction_set(&mut self, set: Arc<InstructionSet<M::Word>>) -> &mut Self {
+        self.extensions = Some(set);
+        self
+    }
+
+    // Runs the instruction at ip if it is one of the extensions
+    pub(super) fn execute_extension(&mut self, ip: usize) -> Result<Signal, Fault> {
+        let word = small(&self.memory.get(ip)).map_err(|_| Fault::UnknownOpcode)?;
+        let set = match self.extensions.clone() {
+            Some(set) => set,
+            None => return Err(Fault::UnknownOpcode),
+        };
+        let extension = set.get(word % 100).ok_or(Fault::UnknownOpcode)?;
+        let params = (1..=extension.roles.len())
+            .map(|n| get_parameter(self.memory.get(ip + n), word / 10i64.pow(n as u32 + 1) % 10))
+            .collect::<Result<Vec<Parameter<M::Word>>, Fault>>()?;
+
+        let mut operands = Operands {
+            roles: extension.roles.clone(),
+            values: Vec::new(),
+            writes: vec![None; params.len()],
+            jump: false,
+            output: None,
+        };
+        for (param, role) in params.iter().zip(&extension.roles) {
+            operands.values.push(match role {
+                Role::Write => M::Word::default(),
+                _ => self.unwrap_value(param.clone())?,
+            });
+        }
+        if let Some(guard) = self.code_guard.as_mut() {
+            guard.executed(ip, params.len() + 1);
+        }
+        if let Some(history) = self.history.as_mut() {
+            history.begin(ip, self.relative_base_offset, self.output.clone());
+        }
+        self.ip += params.len() + 1;
+
+        (extension.exec)(&mut operands).map_err(|e| Fault::Extension(Box::new(e)))?;
+        for (param, value) in params.iter().zip(operands.writes) {
+            if let Some(value) = value {
+                self.store_val(param.clone(), value)?;
+            }
+        }
+        if operands.jump {
+            if let Some(i) = extension.roles.iter().position(|&r| r == Role::Jump) {
+                self.ip = self.address(small(&operands.values[i])?)?;
+                if let Some(profile) = self.profile.as_mut() {
+                    profile.record_jump(ip, self.ip);
+                }
+            }
+        }
+        if let Some(profile) = self.profile.as_mut() {
+            profile.record(ip, extension.name);
+        }
+        Ok(match operands.output {
+            Some(value) => {
+                self.output = Some(value);
+                Signal::ProducedOutput
+            }
+            None => Signal::None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{get_computer, parse_program, IntcodeError};
+
+    fn extended() -> InstructionSet {
+        let mut set: InstructionSet = InstructionSet::new();
+        set.add(10, "DIV", &[Role::Read, Role::Read, Role::Write], |ops| {
+            let quotient = ops.get(0)?.checked_div(*ops.get(1)?);
+            ops.set(2, quotient.ok_or("division by zero")?)
+        })
+        .unwrap()
+        .add(11, "MOD", &[Role::Read, Role::Read, Role::Write], |ops| {
+            let remainder = ops.get(0)?.checked_rem(*ops.get(1)?);
+            ops.set(2, remainder.ok_or("division by zero")?)
+        })
+        .unwrap()
+        // Jumps if the first parameter is less than the second
+        .add(12, "JLT", &[Role::Read, Role::Read, Role::Jump], |ops| {
+            if ops.get(0)? < ops.get(1)? {
+                ops.jump();
+            }
+            Ok(())
+        })
+        .unwrap()
+        .add(13, "DBG", &[Role::Read], |ops| {
+            ops.output(*ops.get(0)?);
+            Ok(())
+        })
+        .unwrap()
+        // Mistakes in the closure: writes to a read parameter, reads one
+        // past the last
+        .add(14, "BAD", &[Role::Read], |ops| ops.set(0, 1))
+        .unwrap()
+        .add(15, "OOB", &[Role::Read], |ops| ops.get(1).map(|_| ()))
+        .unwrap();
+        set
+    }
+
+    fn run(program: &str, input: Vec<i64>) -> Result<Vec<i64>, IntcodeError> {
+        let mut computer = get_computer(&parse_program(program), input);
+        computer.set_instruction_set(Arc::new(extended()));
+        computer.run_till_halt()
+    }
+
+    #[test]
+    fn custom_opcodes_run_alongside_builtins() {
+        // Prints the digits of its input, lowest first
+        let program = "3,100,1011,100,10,101,1010,100,10,100,4,101,10112,0,100,2,99";
+        assert_eq!(run(program, vec![472]), Ok(vec![2, 7, 4]));
+        // DIV with both operands immediate, then a debug print in relative mode
+        assert_eq!(run("1110,17,5,9,109,9,213,0,99,0", vec![]), Ok(vec![3]));
+    }
+
+    #[test]
+    fn errors_from_custom_opcodes() {
+        assert_eq!(
+            run("1110,1,0,5,99,0", vec![]),
+            Err(IntcodeError::Extension {
+                ip: 0,
+                opcode: 1110,
+                message: "division by zero".to_string()
+            })
+        );
+        assert_eq!(
+            run("16,0,99", vec![]),
+            Err(IntcodeError::UnknownOpcode { ip: 0, opcode: 16 })
+        );
+        assert_eq!(
+            run("14,0,99", vec![]),
+            Err(IntcodeError::Extension {
+                ip: 0,
+                opcode: 14,
+                message: "parameter 0 is not written".to_string()
+            })
+        );
+        assert_eq!(
+            run("15,0,99", vec![]),
+            Err(IntcodeError::Extension {
+                ip: 0,
+                opcode: 15,
+                message: "there is no parameter 1".to_string()
+            })
+        );
+        // Without the instruction set the opcode is as unknown as ever
+        let mut computer = get_computer(&parse_program("13,0,99"), vec![]);
+        assert_eq!(
+            computer.run_till_halt(),
+            Err(IntcodeError::UnknownOpcode { ip: 0, opcode: 13 })
+        );
+    }
+
+    #[test]
+    fn builtins_cannot_be_replaced() {
+        let mut set = InstructionSet::<i64>::new();
+        let nop = |_: &mut Operands<i64>| Ok(());
+        assert_eq!(
+            set.add(1, "ADD", &[], nop).err(),
+            Some(RegistryError::Reserved(1))
+        );
+        assert_eq!(
+            set.add(99, "HLT", &[], nop).err(),
+            Some(RegistryError::Reserved(99))
+        );
+        assert_eq!(
+            set.add(100, "X", &[], nop).err(),
+            Some(RegistryError::Reserved(100))
+        );
+        assert_eq!(
+            set.add(43, "BIG", &[Role::Read; 18], nop).err(),
+            Some(RegistryError::TooManyParameters(43))
+        );
+        assert!(set.add(44, "WIDE", &[Role::Read; 17], nop).is_ok());
+        assert!(set.add(42, "NOP", &[], nop).is_ok());
+        assert_eq!(
+            set.add(42, "NOP", &[], nop).err(),
+            Some(RegistryError::Duplicate(42))
+        );
+    }
+}
@@ -0,0 +1,653 @@
+// Differential fuzzing of the VM. `generate` builds a random but well-formed
+// program from a seed: straight-line arithmetic in every parameter mode,
+// forward branches, counted loops, input and output, plus the occasional
+// garbage word or write to an immediate so the error paths get exercised.
+// `check` runs it on a small model interpreter, then on the VM and every other
+// backend, and reports the first one that disagrees or panics.
+//
+// The generator sticks to a small data region and small relative base moves,
+// but nothing stops a program from writing over its own code, so the model
+// run is what decides whether a program is worth checking: one that doesn't
+// stop within `MAX_STEPS`, or that touches memory past `MAX_ADDRESS`, is
+// skipped.
+
+use super::asm::{assemble, AsmError};
+use super::compile::compile;
+use super::memory::{CowMemory, DenseMemory, Memory, SparseMemory};
+use super::snapshot::Snapshot;
+use super::word::Word;
+use super::Signal;
+use super::{get_instruction, saturate, Instruction, IntCodeComputer, IntcodeError, Parameter};
+use num_bigint::BigInt;
+use std::any::Any;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+pub const MAX_STEPS: usize = 20_000;
+pub const MAX_ADDRESS: usize = 1 << 16;
+
+const DATA_BASE: i64 = 256;
+const DATA_LEN: i64 = 32;
+// Loop counters live right after the data region, out of reach of the
+// generated position-mode writes. Each loop sets its counter on entry, so
+// nested loops run their full count every time round.
+const COUNTERS: i64 = 4;
+const MAX_CODE: usize = 200;
+
+// xorshift64*, so a seed always produces the same program
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    pub fn range(&mut self, low: i64, high: i64) -> i64 {
+        low + self.below((high - low + 1) as usize) as i64
+    }
+
+    // True once in `n` times
+    pub fn one_in(&mut self, n: usize) -> bool {
+        self.below(n) == 0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Case {
+    pub seed: u64,
+    pub program: Vec<i64>,
+    pub input: Vec<i64>,
+    // Where the generator put well-formed instructions, for the assembler
+    // round trip
+    pub instructions: Vec<usize>,
+}
+
+struct Generator {
+    rng: Rng,
+    words: Vec<i64>,
+    instructions: Vec<usize>,
+    counters: usize,
+}
+
+impl Generator {
+    fn value(&mut self) -> i64 {
+        match self.rng.below(64) {
+            0 => [i64::MAX, i64::MIN, i64::MAX / 2, -(1 << 40)][self.rng.below(4)],
+            1..=12 => self.rng.range(-1000, 1000),
+            _ => self.rng.range(-20, 20),
+        }
+    }
+
+    fn read(&mut self) -> Parameter {
+        match self.rng.below(8) {
+            0..=2 => Parameter::Immediate(self.value()),
+            3..=5 => Parameter::Position(DATA_BASE + self.rng.range(0, DATA_LEN + COUNTERS - 1)),
+            6 => Parameter::Position(self.rng.range(0, self.words.len() as i64)),
+            _ => Parameter::Relative(self.rng.range(-16, 16)),
+        }
+    }
+
+    fn write(&mut self) -> Parameter {
+        match self.rng.below(32) {
+            0 if self.rng.one_in(16) => Parameter::Immediate(self.value()),
+            0..=15 => Parameter::Position(DATA_BASE + self.rng.range(0, DATA_LEN - 1)),
+            _ => Parameter::Relative(self.rng.range(-16, 16)),
+        }
+    }
+
+    fn emit(&mut self, inst: Instruction) -> usize {
+        let ip = self.words.len();
+        self.instructions.push(ip);
+        self.words.extend(inst.encode());
+        ip
+    }
+
+    fn simple(&mut self) {
+        let inst = match self.rng.below(16) {
+            0..=3 => Instruction::Add((self.read(), self.read(), self.write())),
+            4..=6 => Instruction::Mul((self.read(), self.read(), self.write())),
+            7..=8 => Instruction::LessThan((self.read(), self.read(), self.write())),
+            9..=10 => Instruction::Equals((self.read(), self.read(), self.write())),
+            11 => Instruction::Input(self.write()),
+            12..=13 => Instruction::Output(self.read()),
+            _ => Instruction::RelativeBaseOffset(Parameter::Immediate(self.rng.range(-3, 3))),
+        };
+        self.emit(inst);
+    }
+
+    fn block(&mut self, depth: usize) {
+        for _ in 0..self.rng.range(1, 6) {
+            if self.words.len() > MAX_CODE {
+                return;
+            }
+            match self.rng.below(16) {
+                0 if depth < 2 => self.branch(depth),
+                1 if depth < 2 && self.counters < COUNTERS as usize => self.counted_loop(depth),
+                2 if self.rng.one_in(64) => self.words.push(self.rng.range(-100, 30000)),
+                _ => self.simple(),
+            }
+        }
+    }
+
+    fn branch(&mut self, depth: usize) {
+        let cond = self.read();
+        let ip = match self.rng.one_in(2) {
+            true => self.emit(Instruction::JumpIfTrue((cond, Parameter::Immediate(0)))),
+            false => self.emit(Instruction::JumpIfFalse((cond, Parameter::Immediate(0)))),
+        };
+        self.block(depth + 1);
+        self.words[ip + 2] = self.words.len() as i64;
+    }
+
+    fn counted_loop(&mut self, depth: usize) {
+        let counter = Parameter::Position(DATA_BASE + DATA_LEN + self.counters as i64);
+        self.counters += 1;
+        let count = Parameter::Immediate(self.rng.range(1, 4));
+        self.emit(Instruction::Add((count, Parameter::Immediate(0), counter)));
+        let start = self.words.len() as i64;
+        self.block(depth + 1);
+        self.emit(Instruction::Add((
+            counter,
+            Parameter::Immediate(-1),
+            counter,
+        )));
+        self.emit(Instruction::JumpIfTrue((
+            counter,
+            Parameter::Immediate(start),
+        )));
+    }
+}
+
+pub fn generate(seed: u64) -> Case {
+    let mut gen = Generator {
+        rng: Rng::new(seed),
+        words: Vec::new(),
+        instructions: Vec::new(),
+        counters: 0,
+    };
+    gen.emit(Instruction::RelativeBaseOffset(Parameter::Immediate(
+        DATA_BASE + DATA_LEN / 2,
+    )));
+    while gen.words.len() < MAX_CODE / 2 {
+        gen.block(0);
+    }
+    gen.emit(Instruction::Halt);
+
+    let mut rng = gen.rng;
+    let mut program = gen.words;
+    program.resize(DATA_BASE as usize, 0);
+    for _ in 0..DATA_LEN {
+        program.push(rng.range(-20, 20));
+    }
+    program.resize((DATA_BASE + DATA_LEN + COUNTERS) as usize, 0);
+    let input = (0..rng.below(24)).map(|_| rng.range(-20, 20)).collect();
+    Case {
+        seed,
+        program,
+        input,
+        instructions: gen.instructions,
+    }
+}
+
+// Everything a backend is expected to agree on once the program stops.
+// Memory is compared without trailing zeros, since backends allocate
+// differently.
+#[derive(Debug, PartialEq)]
+pub struct Outcome {
+    pub result: Result<Signal, IntcodeError>,
+    pub outputs: Vec<i64>,
+    pub ip: usize,
+    pub relative_base: i64,
+    pub memory: Vec<i64>,
+}
+
+#[derive(Debug)]
+pub enum Failure {
+    // Ran past `MAX_STEPS` where the model stopped
+    Diverged {
+        backend: &'static str,
+    },
+    Panic {
+        backend: &'static str,
+        message: String,
+    },
+    Mismatch {
+        backend: &'static str,
+        // What it was checked against
+        reference: &'static str,
+        expected: Box<Outcome>,
+        actual: Box<Outcome>,
+    },
+    RoundTrip {
+        ip: usize,
+        instruction: Instruction,
+        assembled: Result<Vec<i64>, AsmError>,
+    },
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Failure::Diverged { backend } => {
+                write!(
+                    f,
+                    "{} ran past {} steps, the model did not",
+                    backend, MAX_STEPS
+                )
+            }
+            Failure::Panic { backend, message } => write!(f, "{} panicked: {}", backend, message),
+            Failure::Mismatch {
+                backend,
+                reference,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} disagrees with the {}\nexpected: {:?}\nactual:   {:?}",
+                backend, reference, expected, actual
+            ),
+            Failure::RoundTrip {
+                ip,
+                instruction,
+                assembled,
+            } => write!(
+                f,
+                "`{}` at {} assembles to {:?}, not {:?}",
+                instruction,
+                ip,
+                assembled,
+                instruction.encode()
+            ),
+        }
+    }
+}
+
+fn outcome<M: Memory>(
+    computer: &IntCodeComputer<M>,
+    result: Result<Signal, IntcodeError>,
+    outputs: &[M::Word],
+) -> Outcome {
+    let mut memory: Vec<i64> = computer.memory().to_vec().iter().map(saturate).collect();
+    while memory.last() == Some(&0) {
+        memory.pop();
+    }
+    Outcome {
+        result,
+        outputs: outputs.iter().map(saturate).collect(),
+        ip: computer.ip(),
+        relative_base: computer.relative_base(),
+        memory,
+    }
+}
+
+fn machine<M: Memory>(case: &Case) -> IntCodeComputer<M> {
+    let mut computer = IntCodeComputer::new();
+    let word = |&w: &i64| M::Word::from_i64(w);
+    computer
+        .load_memory(case.program.iter().map(word).collect())
+        .set_input(case.input.iter().map(word).collect());
+    computer
+}
+
+// Ticks until the program stops, or gives up after `steps` instructions
+fn run_steps<M: Memory>(
+    computer: &mut IntCodeComputer<M>,
+    steps: usize,
+    outputs: &mut Vec<M::Word>,
+) -> Option<Result<Signal, IntcodeError>> {
+    for _ in 0..steps {
+        match computer.tick() {
+            Ok(Signal::ProducedOutput) => outputs.extend(computer.get_output()),
+            Ok(Signal::None) => {}
+            stopped => return Some(stopped),
+        }
+    }
+    None
+}
+
+fn run_to_end<M: Memory>(computer: &mut IntCodeComputer<M>) -> Outcome {
+    let mut outputs = Vec::new();
+    let result = computer.run_with_io(&mut VecDeque::new(), &mut outputs);
+    outcome(computer, result, &outputs)
+}
+
+// The VM's own reference run, and how many steps it took
+fn reference(case: &Case) -> Option<(Outcome, usize)> {
+    let mut computer = machine::<SparseMemory>(case);
+    let mut outputs = Vec::new();
+    for steps in 1..=MAX_STEPS {
+        if let Some(result) = run_steps(&mut computer, 1, &mut outputs) {
+            return Some((outcome(&computer, result, &outputs), steps));
+        }
+    }
+    None
+}
+
+// A deliberately plain second implementation of the instruction set, written
+// straight from the spec and sharing nothing with the VM but its error type,
+// so that a bug in the VM's shared parameter handling can't hide by showing
+// up in every backend at once
+fn model(case: &Case) -> Option<Outcome> {
+    let mut memory: BTreeMap<usize, i64> = case.program.iter().cloned().enumerate().collect();
+    let mut input: VecDeque<i64> = case.input.iter().cloned().collect();
+    let mut outputs = Vec::new();
+    let (mut ip, mut rb) = (0, 0);
+    let mut result = None;
+    for _ in 0..MAX_STEPS {
+        let at = ip;
+        result = model_step(&mut memory, &mut ip, &mut rb, &mut input, &mut outputs).transpose();
+        if result.is_some() {
+            // A fault leaves ip on the instruction that raised it
+            if let Some(Err(_)) = result {
+                ip = at;
+            }
+            break;
+        }
+    }
+    match memory.keys().next_back() {
+        Some(&last) if last >= MAX_ADDRESS => None,
+        _ => {
+            let mut words: Vec<i64> = (0..memory.keys().next_back().map_or(0, |a| a + 1))
+                .map(|a| memory.get(&a).cloned().unwrap_or(0))
+                .collect();
+            while words.last() == Some(&0) {
+                words.pop();
+            }
+            Some(Outcome {
+                result: result?,
+                outputs,
+                ip,
+                relative_base: rb,
+                memory: words,
+            })
+        }
+    }
+}
+
+// `Ok(None)` while the program keeps running
+fn model_step(
+    memory: &mut BTreeMap<usize, i64>,
+    ip: &mut usize,
+    rb: &mut i64,
+    input: &mut VecDeque<i64>,
+    outputs: &mut Vec<i64>,
+) -> Result<Option<Signal>, IntcodeError> {
+    let at = *ip;
+    let get = |memory: &BTreeMap<usize, i64>, a: usize| memory.get(&a).cloned().unwrap_or(0);
+    let opcode = get(memory, at);
+    let arity = match opcode % 100 {
+        1 | 2 | 7 | 8 => 3,
+        5 | 6 => 2,
+        3 | 4 | 9 => 1,
+        99 => 0,
+        _ => return Err(IntcodeError::UnknownOpcode { ip: at, opcode }),
+    };
+    let mut modes = Vec::new();
+    for n in 1..=arity {
+        let mode = opcode / 10i64.pow(n + 1) % 10;
+        if mode > 2 {
+            return Err(IntcodeError::UnknownParameterMode {
+                ip: at,
+                opcode,
+                mode,
+            });
+        }
+        modes.push(mode);
+    }
+    if opcode % 100 == 3 && input.is_empty() {
+        return Ok(Some(Signal::NeedsInput));
+    }
+    *ip += arity as usize + 1;
+
+    let negative = |address| IntcodeError::NegativeAddress {
+        ip: at,
+        opcode,
+        address,
+    };
+    let overflow = || IntcodeError::Overflow { ip: at, opcode };
+    let address = |memory: &BTreeMap<usize, i64>, n: usize| -> Result<usize, IntcodeError> {
+        let raw = get(memory, at + n);
+        let address = match modes[n - 1] {
+            0 => raw,
+            1 => {
+                return Err(IntcodeError::WriteToImmediate {
+                    ip: at,
+                    opcode,
+                    operand: raw,
+                })
+            }
+            _ => raw.checked_add(*rb).ok_or_else(overflow)?,
+        };
+        match address {
+            a if a < 0 => Err(negative(a)),
+            a => Ok(a as usize),
+        }
+    };
+    let read = |memory: &BTreeMap<usize, i64>, n: usize| match modes[n - 1] {
+        1 => Ok(get(memory, at + n)),
+        _ => Ok(get(memory, address(memory, n)?)),
+    };
+
+    let result = match opcode % 100 {
+        1 | 2 | 7 | 8 => {
+            let (a, b) = (read(memory, 1)?, read(memory, 2)?);
+            let value = match opcode % 100 {
+                1 => a.checked_add(b).ok_or_else(overflow)?,
+                2 => a.checked_mul(b).ok_or_else(overflow)?,
+                7 => (a < b) as i64,
+                _ => (a == b) as i64,
+            };
+            let target = address(memory, 3)?;
+            memory.insert(target, value);
+            None
+        }
+        3 => {
+            let target = address(memory, 1)?;
+            memory.insert(target, input.pop_front().unwrap());
+            None
+        }
+        4 => {
+            outputs.push(read(memory, 1)?);
+            Some(Signal::ProducedOutput)
+        }
+        5 | 6 => {
+            if (read(memory, 1)? != 0) == (opcode % 100 == 5) {
+                let target = read(memory, 2)?;
+                *ip = match target {
+                    t if t < 0 => return Err(negative(t)),
+                    t => t as usize,
+                };
+            }
+            None
+        }
+        9 => {
+            *rb = rb.checked_add(read(memory, 1)?).ok_or_else(overflow)?;
+            None
+        }
+        _ => {
+            // Halting leaves ip on the HLT
+            *ip = at;
+            Some(Signal::Halt)
+        }
+    };
+    // Outputs are collected above, so only stops are reported
+    Ok(result.filter(|s| *s != Signal::ProducedOutput))
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "non-string panic".to_string(),
+        },
+    }
+}
+
+fn round_trip(case: &Case) -> Result<(), Failure> {
+    for &ip in &case.instructions {
+        let instruction = match get_instruction(&case.program, ip) {
+            Ok(inst) => inst,
+            Err(e) => panic!("generated instruction at {} does not decode: {}", ip, e),
+        };
+        let assembled = assemble(&instruction.to_string());
+        if assembled.as_ref().ok() != Some(&case.program[ip..ip + instruction.size()].to_vec()) {
+            return Err(Failure::RoundTrip {
+                ip,
+                instruction,
+                assembled,
+            });
+        }
+    }
+    Ok(())
+}
+
+type Backend<'a> = (&'static str, Box<dyn Fn() -> Outcome + 'a>);
+
+// Runs the case everywhere and returns the outcome they all agreed on, or
+// `None` if the case was skipped
+pub fn check(case: &Case) -> Result<Option<Outcome>, Failure> {
+    round_trip(case)?;
+    let modelled = match model(case) {
+        Some(outcome) => outcome,
+        None => return Ok(None),
+    };
+    let (expected, steps) = match catch_unwind(|| reference(case)) {
+        Ok(Some(reference)) => reference,
+        Ok(None) => {
+            return Err(Failure::Diverged {
+                backend: "reference",
+            })
+        }
+        Err(payload) => {
+            return Err(Failure::Panic {
+                backend: "reference",
+                message: panic_message(payload),
+            })
+        }
+    };
+    if modelled != expected {
+        return Err(Failure::Mismatch {
+            backend: "reference",
+            reference: "model",
+            expected: Box::new(modelled),
+            actual: Box::new(expected),
+        });
+    }
+    let overflowed = matches!(expected.result, Err(IntcodeError::Overflow { .. }));
+
+    let mut backends: Vec<Backend> = vec![
+        ("reference again", Box::new(|| reference(case).unwrap().0)),
+        (
+            "dense",
+            Box::new(|| run_to_end(&mut machine::<DenseMemory>(case))),
+        ),
+        (
+            "decode cache",
+            Box::new(|| {
+                let mut computer = machine::<DenseMemory>(case);
+                computer.enable_decode_cache();
+                run_to_end(&mut computer)
+            }),
+        ),
+        (
+            "copy on write",
+            Box::new(|| run_to_end(&mut machine::<CowMemory>(case))),
+        ),
+        (
+            "compiled",
+            Box::new(|| {
+                let program = compile(&case.program);
+                let mut machine = program.machine(case.input.clone());
+                let mut outputs = Vec::new();
+                let result = machine.run_with_io(&mut VecDeque::new(), &mut outputs);
+                outcome(machine.computer(), result, &outputs)
+            }),
+        ),
+        (
+            "resumed from snapshot",
+            Box::new(move || {
+                let mut computer = machine::<DenseMemory>(case);
+                let mut outputs = Vec::new();
+                if let Some(result) = run_steps(&mut computer, steps / 2, &mut outputs) {
+                    return outcome(&computer, result, &outputs);
+                }
+                let snapshot: Snapshot = computer.snapshot();
+                let mut resumed = IntCodeComputer::from_snapshot(&snapshot);
+                let rest = run_to_end(&mut resumed);
+                outputs.extend(rest.outputs);
+                Outcome { outputs, ..rest }
+            }),
+        ),
+    ];
+    // Wider words only agree as long as nothing overflows an i64
+    if !overflowed {
+        backends.push((
+            "i128",
+            Box::new(|| run_to_end(&mut machine::<DenseMemory<i128>>(case))),
+        ));
+        backends.push((
+            "BigInt",
+            Box::new(|| run_to_end(&mut machine::<DenseMemory<BigInt>>(case))),
+        ));
+    }
+
+    for (backend, run) in backends {
+        match catch_unwind(AssertUnwindSafe(run)) {
+            Ok(actual) if actual == expected => {}
+            Ok(actual) => {
+                return Err(Failure::Mismatch {
+                    backend,
+                    reference: "reference",
+                    expected: Box::new(expected),
+                    actual: Box::new(actual),
+                })
+            }
+            Err(payload) => {
+                return Err(Failure::Panic {
+                    backend,
+                    message: panic_message(payload),
+                })
+            }
+        }
+    }
+    Ok(Some(expected))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generation_is_deterministic() {
+        assert_eq!(generate(7), generate(7));
+        assert_ne!(generate(7).program, generate(8).program);
+    }
+
+    #[test]
+    fn backends_agree_on_random_programs() {
+        let mut halted = 0;
+        for seed in 0..300 {
+            let case = generate(seed);
+            match check(&case) {
+                Ok(Some(outcome)) => halted += (outcome.result == Ok(Signal::Halt)) as usize,
+                Ok(None) => {}
+                Err(failure) => panic!("seed {}: {}", seed, failure),
+            }
+        }
+        // Most programs should run to the end rather than fault early
+        assert!(halted > 100, "only {} of 300 programs halted", halted);
+    }
+}
@@ -0,0 +1,180 @@
+// Undo log for stepping an IntCodeComputer backwards. Every executed
+// instruction pushes a record of the state it is about to change, so undoing
+// it is a matter of putting those values back in reverse order. The record is
+// only pushed once the instruction succeeds, as one that faults changes
+// nothing to undo.
+
+use super::memory::Memory;
+use super::word::Word;
+use super::IntCodeComputer;
+use std::collections::VecDeque;
+
+#[derive(Clone)]
+struct Undo<W: Word> {
+    ip: usize,
+    relative_base: i64,
+    output: Option<W>,
+    input: Option<W>,
+    writes: Vec<(usize, W)>,
+}
+
+#[derive(Clone)]
+pub struct History<W: Word = i64> {
+    records: VecDeque<Undo<W>>,
+    // The instruction being executed
+    pending: Option<Undo<W>>,
+    capacity: usize,
+}
+
+impl<W: Word> History<W> {
+    pub(super) fn begin(&mut self, ip: usize, relative_base: i64, output: Option<W>) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.pending = Some(Undo {
+            ip,
+            relative_base,
+            output,
+            input: None,
+            writes: Vec::new(),
+        });
+    }
+
+    pub(super) fn record_write(&mut self, addr: usize, old: W) {
+        if let Some(undo) = self.pending.as_mut() {
+            undo.writes.push((addr, old));
+        }
+    }
+
+    pub(super) fn record_input(&mut self, value: W) {
+        if let Some(undo) = self.pending.as_mut() {
+            undo.input = Some(value);
+        }
+    }
+
+    // Keeps the record of an instruction that ran, or drops it if it faulted
+    pub(super) fn end(&mut self, ran: bool) {
+        let undo = match self.pending.take() {
+            Some(undo) if ran => undo,
+            _ => return,
+        };
+        if self.records.len() >= self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(undo);
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.records.clear();
+    }
+}
+
+impl<M: Memory> IntCodeComputer<M> {
+    // Keeps enough history to undo the last `capacity` instructions. With a
+    // capacity of 0 nothing is recorded.
+    pub fn enable_history(&mut self, capacity: usize) -> &mut Self {
+        self.history = Some(History {
+            records: VecDeque::new(),
+            pending: None,
+            capacity,
+        });
+        self
+    }
+
+    // How many instructions can currently be undone
+    pub fn history_len(&self) -> usize {
+        self.history.as_ref().map_or(0, |h| h.records.len())
+    }
+
+    // Undoes the last executed instruction, returning false when there is no
+    // history left
+    pub fn step_back(&mut self) -> bool {
+        let undo = match self.history.as_mut().and_then(|h| h.records.pop_back()) {
+            Some(undo) => undo,
+            None => return false,
+        };
+        for (addr, old) in undo.writes.into_iter().rev() {
+            self.store_value_at_pos(addr, old);
+        }
+        if let Some(value) = undo.input {
+            self.input.push_front(value);
+        }
+        self.ip = undo.ip;
+        self.relative_base_offset = undo.relative_base;
+        self.output = undo.output;
+        self.halted = false;
+        true
+    }
+
+    // Returns the number of instructions actually undone
+    pub fn step_back_n(&mut self, n: usize) -> usize {
+        (0..n).take_while(|_| self.step_back()).count()
+    }
+
+    // Rewinds to just before the most recent instruction that wrote `addr`
+    // and returns its ip. If no write is found the machine is left untouched.
+    pub fn run_back_to_write(&mut self, addr: usize) -> Option<usize> {
+        let records = &self.history.as_ref()?.records;
+        let back = records
+            .iter()
+            .rev()
+            .position(|undo| undo.writes.iter().any(|&(a, _)| a == addr))?;
+        self.step_back_n(back + 1);
+        Some(self.ip)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intcode::memory::Memory;
+    use crate::intcode::{get_computer, parse_program};
+
+    #[test]
+    fn steps_back_over_writes_and_input() {
+        let program = parse_program("3,11,109,5,1001,11,1,11,204,6,99,0");
+        let mut computer = get_computer(&program, vec![7]);
+        computer.enable_history(100);
+        assert_eq!(computer.run_till_halt(), Ok(vec![8]));
+
+        assert_eq!(computer.run_back_to_write(11), Some(4));
+        assert_eq!(computer.get_value_at_pos(11), 7);
+        assert_eq!(computer.relative_base(), 5);
+
+        assert_eq!(computer.step_back_n(10), 2);
+        assert_eq!(computer.memory().to_vec(), program);
+        assert_eq!(computer.pending_input(), &[7]);
+        assert_eq!(computer.run_till_halt(), Ok(vec![8]));
+    }
+
+    #[test]
+    fn faults_leave_no_record() {
+        // The second ADD writes to -1
+        let mut computer = get_computer(&parse_program("1101,1,1,9,1101,1,1,-1,99,0"), vec![]);
+        computer.enable_history(10);
+        assert!(computer.run().is_err());
+        assert_eq!(computer.history_len(), 1);
+        assert!(computer.step_back());
+        assert_eq!((computer.ip(), computer.get_value_at_pos(9)), (0, 0));
+    }
+
+    #[test]
+    fn history_is_bounded() {
+        let program = parse_program("1101,1,1,0,1101,2,2,0,99");
+        let mut computer = get_computer(&program, vec![]);
+        computer.enable_history(1);
+        computer.tick().unwrap();
+        computer.tick().unwrap();
+        assert_eq!(computer.history_len(), 1);
+        // The second ADD can be undone, the first one was evicted
+        assert!(computer.step_back());
+        assert_eq!((computer.ip(), computer.get_value_at_pos(0)), (4, 2));
+        assert!(!computer.step_back());
+        assert_eq!((computer.ip(), computer.get_value_at_pos(0)), (4, 2));
+
+        let mut computer = get_computer(&program, vec![]);
+        computer.enable_history(0);
+        computer.run_till_halt().unwrap();
+        assert_eq!(computer.history_len(), 0);
+        assert!(!computer.step_back());
+    }
+}
@@ -0,0 +1,151 @@
+// Sources and sinks that a machine can be wired to instead of feeding input
+// and collecting output by hand. `run_with_io` pulls from the source whenever
+// the machine's own input queue runs dry and forwards every output to the sink,
+// so nothing is lost to the single output slot.
+//
+// A source returning `None` means nothing is available yet, and the machine
+// pauses with `Signal::NeedsInput`. A `Receiver` blocks until a value arrives,
+// so it only reports `None` once every sender is gone.
+
+use super::{IntCodeComputer, IntcodeError, Signal};
+use crate::intcode::memory::Memory;
+use std::collections::VecDeque;
+use std::sync::mpsc::{Receiver, Sender};
+
+pub trait InputSource<W = i64> {
+    fn next_input(&mut self) -> Option<W>;
+}
+
+pub trait OutputSink<W = i64> {
+    fn emit(&mut self, value: W);
+}
+
+impl<W> InputSource<W> for VecDeque<W> {
+    fn next_input(&mut self) -> Option<W> {
+        self.pop_front()
+    }
+}
+
+impl<W> InputSource<W> for Receiver<W> {
+    fn next_input(&mut self) -> Option<W> {
+        self.recv().ok()
+    }
+}
+
+impl<W, F: FnMut() -> Option<W>> InputSource<W> for F {
+    fn next_input(&mut self) -> Option<W> {
+        self()
+    }
+}
+
+impl<W> OutputSink<W> for VecDeque<W> {
+    fn emit(&mut self, value: W) {
+        self.push_back(value);
+    }
+}
+
+impl<W> OutputSink<W> for Vec<W> {
+    fn emit(&mut self, value: W) {
+        self.push(value);
+    }
+}
+
+// A receiver that has hung up is not the sending machine's problem
+impl<W> OutputSink<W> for Sender<W> {
+    fn emit(&mut self, value: W) {
+        let _ = self.send(value);
+    }
+}
+
+impl<W, F: FnMut(W)> OutputSink<W> for F {
+    fn emit(&mut self, value: W) {
+        self(value)
+    }
+}
+
+impl<M: Memory> IntCodeComputer<M> {
+    // Runs until the program halts, needs input the source cannot provide, or
+    // pauses at a watchpoint
+    pub fn run_with_io(
+        &mut self,
+        input: &mut impl InputSource<M::Word>,
+        output: &mut impl OutputSink<M::Word>,
+    ) -> Result<Signal, IntcodeError> {
+        self.pump(input, output, Self::run)
+    }
+
+    // Wires up the IO around any way of running the machine until a signal
+    pub(super) fn pump(
+        &mut self,
+        input: &mut impl InputSource<M::Word>,
+        output: &mut impl OutputSink<M::Word>,
+        mut run: impl FnMut(&mut Self) -> Result<Signal, IntcodeError>,
+    ) -> Result<Signal, IntcodeError> {
+        loop {
+            match run(self)? {
+                Signal::NeedsInput => match input.next_input() {
+                    Some(value) => self.feed_input(value),
+                    None => return Ok(Signal::NeedsInput),
+                },
+                Signal::ProducedOutput => output.emit(self.get_output().unwrap()),
+                Signal::None => {}
+                signal => return Ok(signal),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{get_computer, parse_program};
+    use std::sync::mpsc::channel;
+    use std::thread;
+
+    // Echoes inputs doubled until it reads a zero
+    const DOUBLER: &str = "3,15,1006,15,14,102,2,15,15,4,15,1105,1,0,99,0";
+
+    #[test]
+    fn closures_as_source_and_sink() {
+        let mut inputs = vec![0, 3, 2, 1];
+        let mut seen = Vec::new();
+        let mut computer = get_computer(&parse_program(DOUBLER), vec![]);
+        let signal = computer
+            .run_with_io(&mut || inputs.pop(), &mut |v| seen.push(v))
+            .unwrap();
+        assert_eq!(signal, Signal::Halt);
+        assert_eq!(seen, vec![2, 4, 6]);
+    }
+
+    #[test]
+    fn pauses_when_queue_runs_dry() {
+        let mut computer = get_computer(&parse_program(DOUBLER), vec![]);
+        let mut inputs = VecDeque::from(vec![5]);
+        let mut outputs = VecDeque::new();
+        let signal = computer.run_with_io(&mut inputs, &mut outputs);
+        assert_eq!(signal, Ok(Signal::NeedsInput));
+        assert_eq!(outputs, vec![10]);
+    }
+
+    #[test]
+    fn machines_wired_by_channels() {
+        let program = parse_program(DOUBLER);
+        let (to_first, mut first_in) = channel();
+        let (mut first_out, mut second_in) = channel();
+        let (mut second_out, results) = channel();
+
+        let mut first = get_computer(&program, vec![]);
+        let mut second = get_computer(&program, vec![]);
+        let handles = vec![
+            thread::spawn(move || first.run_with_io(&mut first_in, &mut first_out)),
+            thread::spawn(move || second.run_with_io(&mut second_in, &mut second_out)),
+        ];
+        for value in &[1, 2, 0] {
+            to_first.send(*value).unwrap();
+        }
+        let signals: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        // The second machine never sees the zero and stops once its sender hangs up
+        assert_eq!(signals, vec![Ok(Signal::Halt), Ok(Signal::NeedsInput)]);
+        assert_eq!(results.iter().collect::<Vec<_>>(), vec![4, 8]);
+    }
+}
@@ -0,0 +1,209 @@
+// Memory backends for IntCodeComputer. Every backend reads unwritten
+// addresses as zero and grows on demand; they differ in how they store it.
+//
+// - `DenseMemory` is a plain Vec and the fastest for ordinary programs, but a
+//   write to a huge address allocates everything below it. Writes from
+//   `DENSE_LIMIT` up fail with `IntcodeError::AddressTooLarge` instead.
+// - `SparseMemory` only allocates the fixed-size pages that are touched.
+// - `CowMemory` is paged like `SparseMemory`, but pages are shared between
+//   clones until one side writes to them, so forking a machine is cheap.
+//
+// Only `DenseMemory` can hold words other than i64.
+
+use super::word::Word;
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+pub const PAGE_SIZE: usize = 1024;
+
+// 512 MiB of i64 words
+pub const DENSE_LIMIT: usize = 1 << 26;
+
+pub trait Memory: Clone + Default + Debug + PartialEq {
+    type Word: Word;
+
+    // Programs can only write below this address
+    const ADDRESS_LIMIT: usize = usize::MAX;
+
+    fn get(&self, addr: usize) -> Self::Word;
+
+    fn set(&mut self, addr: usize, value: Self::Word);
+
+    // Contiguous runs of allocated words in address order. Anything outside
+    // the runs reads as zero.
+    fn runs(&self) -> Vec<(usize, &[Self::Word])>;
+
+    fn from_program(program: Vec<Self::Word>) -> Self {
+        let mut memory = Self::default();
+        for (addr, word) in program.into_iter().enumerate() {
+            memory.set(addr, word);
+        }
+        memory
+    }
+
+    // Dense copy up to the last allocated word
+    fn to_vec(&self) -> Vec<Self::Word> {
+        let mut words = Vec::new();
+        for (start, run) in self.runs() {
+            words.resize(start, Self::Word::default());
+            words.extend_from_slice(run);
+        }
+        words
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DenseMemory<W: Word = i64>(Vec<W>);
+
+impl<W: Word> Memory for DenseMemory<W> {
+    type Word = W;
+
+    const ADDRESS_LIMIT: usize = DENSE_LIMIT;
+
+    fn get(&self, addr: usize) -> W {
+        self.0.get(addr).cloned().unwrap_or_default()
+    }
+
+    fn set(&mut self, addr: usize, value: W) {
+        if self.0.len() <= addr {
+            self.0.resize(addr + 1, W::default());
+        }
+        self.0[addr] = value;
+    }
+
+    fn runs(&self) -> Vec<(usize, &[W])> {
+        vec![(0, &self.0[..])]
+    }
+
+    fn from_program(program: Vec<W>) -> Self {
+        DenseMemory(program)
+    }
+}
+
+pub trait Page: Clone + Debug + PartialEq {
+    fn zeroed() -> Self;
+
+    fn words(&self) -> &[i64; PAGE_SIZE];
+
+    fn words_mut(&mut self) -> &mut [i64; PAGE_SIZE];
+}
+
+impl Page for Box<[i64; PAGE_SIZE]> {
+    fn zeroed() -> Self {
+        Box::new([0; PAGE_SIZE])
+    }
+
+    fn words(&self) -> &[i64; PAGE_SIZE] {
+        self
+    }
+
+    fn words_mut(&mut self) -> &mut [i64; PAGE_SIZE] {
+        self
+    }
+}
+
+impl Page for Arc<[i64; PAGE_SIZE]> {
+    fn zeroed() -> Self {
+        Arc::new([0; PAGE_SIZE])
+    }
+
+    fn words(&self) -> &[i64; PAGE_SIZE] {
+        self
+    }
+
+    // Copies the page first if another clone still shares it
+    fn words_mut(&mut self) -> &mut [i64; PAGE_SIZE] {
+        Arc::make_mut(self)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PagedMemory<P: Page> {
+    pages: BTreeMap<usize, P>,
+}
+
+impl<P: Page> Default for PagedMemory<P> {
+    fn default() -> Self {
+        PagedMemory {
+            pages: BTreeMap::new(),
+        }
+    }
+}
+
+impl<P: Page> PagedMemory<P> {
+    pub fn pages_allocated(&self) -> usize {
+        self.pages.len()
+    }
+}
+
+impl<P: Page> Memory for PagedMemory<P> {
+    type Word = i64;
+
+    fn get(&self, addr: usize) -> i64 {
+        self.pages
+            .get(&(addr / PAGE_SIZE))
+            .map_or(0, |page| page.words()[addr % PAGE_SIZE])
+    }
+
+    fn set(&mut self, addr: usize, value: i64) {
+        let page = self.pages.entry(addr / PAGE_SIZE).or_insert_with(P::zeroed);
+        page.words_mut()[addr % PAGE_SIZE] = value;
+    }
+
+    fn runs(&self) -> Vec<(usize, &[i64])> {
+        self.pages
+            .iter()
+            .map(|(n, page)| (n * PAGE_SIZE, &page.words()[..]))
+            .collect()
+    }
+}
+
+pub type SparseMemory = PagedMemory<Box<[i64; PAGE_SIZE]>>;
+
+pub type CowMemory = PagedMemory<Arc<[i64; PAGE_SIZE]>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{parse_program, IntCodeComputer, IntcodeError};
+
+    #[test]
+    fn sparse_memory_allocates_touched_pages() {
+        let mut memory = SparseMemory::from_program(vec![1, 2, 3]);
+        memory.set(1_000_000_000, 7);
+        assert_eq!(memory.get(1_000_000_000), 7);
+        assert_eq!(memory.get(999_999_999), 0);
+        assert_eq!(memory.get(2), 3);
+        assert_eq!(memory.pages_allocated(), 2);
+    }
+
+    #[test]
+    fn dense_memory_refuses_huge_addresses() {
+        let program = parse_program("1101,1,1,4611686018427387904,99");
+        let mut computer = IntCodeComputer::<DenseMemory>::new();
+        computer.load_memory(program.clone());
+        assert_eq!(
+            computer.run_till_halt(),
+            Err(IntcodeError::AddressTooLarge {
+                ip: 0,
+                opcode: 1101,
+                address: 1 << 62
+            })
+        );
+        let mut computer = IntCodeComputer::<SparseMemory>::new();
+        computer.load_memory(program);
+        assert_eq!(computer.run_till_halt(), Ok(vec![]));
+        assert_eq!(computer.get_value_at_pos(1 << 62), 2);
+    }
+
+    #[test]
+    fn cow_memory_shares_pages_until_written() {
+        let mut memory = CowMemory::from_program(vec![1, 2, 3]);
+        let fork = memory.clone();
+        assert!(Arc::ptr_eq(&memory.pages[&0], &fork.pages[&0]));
+        memory.set(0, 10);
+        assert!(!Arc::ptr_eq(&memory.pages[&0], &fork.pages[&0]));
+        assert_eq!((memory.get(0), fork.get(0)), (10, 1));
+    }
+}
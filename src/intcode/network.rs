@@ -0,0 +1,429 @@
+// Networks of machines described by name and wired together.
+//
+// A plain node passes every output it produces to the nodes it has edges to.
+// An addressed node is the packet-switched kind: it is started with its
+// address as input, reads -1 whenever its inbox is empty, and writes its
+// output as (destination, x, y) triples. Packets sent to the NAT address are
+// held back, and once the whole network goes idle the last one is passed on
+// to address 0.
+//
+// Everything runs on the calling thread in rounds. In each round every node
+// runs until it halts or blocks on input, so the order of delivery is
+// deterministic.
+
+use super::{get_computer, IntCodeComputer, IntcodeError, Signal};
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum NetworkError {
+    DuplicateNode(String),
+    UnknownNode(String),
+    DuplicateAddress(i64),
+    EdgeFromAddressedNode(String),
+    Machine { node: String, error: IntcodeError },
+    Halted,
+    // Idle with nothing for the NAT to pass on
+    Idle,
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetworkError::DuplicateNode(name) => write!(f, "node `{}` defined twice", name),
+            NetworkError::UnknownNode(name) => write!(f, "no node named `{}`", name),
+            NetworkError::DuplicateAddress(address) => {
+                write!(f, "address {} used by more than one node", address)
+            }
+            NetworkError::EdgeFromAddressedNode(name) => {
+                write!(f, "`{}` sends packets and cannot have edges", name)
+            }
+            NetworkError::Machine { node, error } => write!(f, "{}: {}", node, error),
+            NetworkError::Halted => write!(f, "every node halted"),
+            NetworkError::Idle => write!(f, "the network went idle before the NAT got a packet"),
+        }
+    }
+}
+
+impl Error for NetworkError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Packet {
+    pub from: i64,
+    pub dest: i64,
+    pub x: i64,
+    pub y: i64,
+}
+
+struct NodeSpec {
+    name: String,
+    program: Vec<i64>,
+    input: Vec<i64>,
+    address: Option<i64>,
+}
+
+#[derive(Default)]
+pub struct NetworkBuilder {
+    nodes: Vec<NodeSpec>,
+    edges: Vec<(String, String)>,
+    nat: Option<i64>,
+}
+
+impl NetworkBuilder {
+    pub fn new() -> Self {
+        NetworkBuilder::default()
+    }
+
+    pub fn node(&mut self, name: &str, program: &[i64], input: Vec<i64>) -> &mut Self {
+        self.nodes.push(NodeSpec {
+            name: name.to_string(),
+            program: program.to_vec(),
+            input,
+            address: None,
+        });
+        self
+    }
+
+    pub fn addressed_node(&mut self, name: &str, program: &[i64], address: i64) -> &mut Self {
+        self.nodes.push(NodeSpec {
+            name: name.to_string(),
+            program: program.to_vec(),
+            input: vec![address],
+            address: Some(address),
+        });
+        self
+    }
+
+    pub fn edge(&mut self, from: &str, to: &str) -> &mut Self {
+        self.edges.push((from.to_string(), to.to_string()));
+        self
+    }
+
+    pub fn nat(&mut self, address: i64) -> &mut Self {
+        self.nat = Some(address);
+        self
+    }
+
+    pub fn build(&self) -> Result<Network, NetworkError> {
+        let mut index = HashMap::new();
+        let mut addresses = HashMap::new();
+        let mut nodes = Vec::new();
+        for (i, spec) in self.nodes.iter().enumerate() {
+            if index.insert(spec.name.clone(), i).is_some() {
+                return Err(NetworkError::DuplicateNode(spec.name.clone()));
+            }
+            if let Some(address) = spec.address {
+                if addresses.insert(address, i).is_some() || Some(address) == self.nat {
+                    return Err(NetworkError::DuplicateAddress(address));
+                }
+            }
+            nodes.push(Node {
+                name: spec.name.clone(),
+                computer: get_computer(&spec.program, spec.input.clone()),
+                inbox: VecDeque::new(),
+                address: spec.address,
+                targets: Vec::new(),
+                partial: Vec::new(),
+                outputs: Vec::new(),
+                halted: false,
+            });
+        }
+
+        for (from, to) in &self.edges {
+            let lookup = |name: &String| {
+                index
+                    .get(name)
+                    .copied()
+                    .ok_or_else(|| NetworkError::UnknownNode(name.clone()))
+            };
+            let (from, to) = (lookup(from)?, lookup(to)?);
+            if nodes[from].address.is_some() {
+                return Err(NetworkError::EdgeFromAddressedNode(
+                    nodes[from].name.clone(),
+                ));
+            }
+            nodes[from].targets.push(to);
+        }
+
+        Ok(Network {
+            nodes,
+            index,
+            addresses,
+            nat: self.nat,
+            nat_held: None,
+            nat_sent: Vec::new(),
+            packets: Vec::new(),
+        })
+    }
+}
+
+struct Node {
+    name: String,
+    computer: IntCodeComputer,
+    inbox: VecDeque<i64>,
+    address: Option<i64>,
+    targets: Vec<usize>,
+    // Output of an addressed node that does not make up a whole packet yet
+    partial: Vec<i64>,
+    outputs: Vec<i64>,
+    halted: bool,
+}
+
+pub struct Network {
+    nodes: Vec<Node>,
+    index: HashMap<String, usize>,
+    addresses: HashMap<i64, usize>,
+    nat: Option<i64>,
+    nat_held: Option<Packet>,
+    nat_sent: Vec<Packet>,
+    packets: Vec<Packet>,
+}
+
+impl Network {
+    // Queues input for a node, e.g. the signal that starts a day 7 chain
+    pub fn send(&mut self, name: &str, value: i64) -> Result<(), NetworkError> {
+        let i = self.lookup(name)?;
+        self.nodes[i].inbox.push_back(value);
+        Ok(())
+    }
+
+    // Everything the node has written so far
+    pub fn outputs(&self, name: &str) -> Result<&[i64], NetworkError> {
+        Ok(&self.nodes[self.lookup(name)?].outputs)
+    }
+
+    // Every packet sent by an addressed node, including undeliverable ones
+    pub fn packets(&self) -> &[Packet] {
+        &self.packets
+    }
+
+    // Packets the NAT passed on to address 0 when the network went idle
+    pub fn nat_sent(&self) -> &[Packet] {
+        &self.nat_sent
+    }
+
+    pub fn all_halted(&self) -> bool {
+        self.nodes.iter().all(|node| node.halted)
+    }
+
+    fn lookup(&self, name: &str) -> Result<usize, NetworkError> {
+        self.index
+            .get(name)
+            .copied()
+            .ok_or_else(|| NetworkError::UnknownNode(name.to_string()))
+    }
+
+    // Runs every node once and returns whether the network was idle, meaning
+    // no node received or sent anything. An idle round wakes the NAT if it is
+    // holding a packet.
+    pub fn round(&mut self) -> Result<bool, NetworkError> {
+        let mut idle = true;
+        for i in 0..self.nodes.len() {
+            let node = &mut self.nodes[i];
+            if node.halted {
+                continue;
+            }
+            let received = !node.inbox.is_empty();
+            let addressed = node.address.is_some();
+            let inbox = &mut node.inbox;
+            let mut offered_idle = false;
+            let mut input = || {
+                inbox.pop_front().or_else(|| {
+                    if addressed && !offered_idle {
+                        offered_idle = true;
+                        return Some(-1);
+                    }
+                    None
+                })
+            };
+            let mut sent = Vec::new();
+            let signal = node
+                .computer
+                .run_with_io(&mut input, &mut sent)
+                .map_err(|error| NetworkError::Machine {
+                    node: node.name.clone(),
+                    error,
+                })?;
+            node.halted = signal == Signal::Halt;
+            node.outputs.extend_from_slice(&sent);
+            if received || !sent.is_empty() {
+                idle = false;
+            }
+            self.route(i, sent);
+        }
+
+        if idle {
+            if let Some(packet) = self.nat_held {
+                self.nat_sent.push(packet);
+                self.deliver(packet);
+                return Ok(true);
+            }
+        }
+        Ok(idle)
+    }
+
+    fn route(&mut self, from: usize, sent: Vec<i64>) {
+        let source = match self.nodes[from].address {
+            Some(address) => address,
+            None => {
+                for t in 0..self.nodes[from].targets.len() {
+                    let target = self.nodes[from].targets[t];
+                    self.nodes[target].inbox.extend(&sent);
+                }
+                return;
+            }
+        };
+        let partial = &mut self.nodes[from].partial;
+        partial.extend(sent);
+        let whole = partial.len() / 3 * 3;
+        let words: Vec<i64> = partial.drain(..whole).collect();
+        for chunk in words.chunks(3) {
+            let packet = Packet {
+                from: source,
+                dest: chunk[0],
+                x: chunk[1],
+                y: chunk[2],
+            };
+            self.packets.push(packet);
+            if Some(packet.dest) == self.nat {
+                self.nat_held = Some(packet);
+            } else {
+                self.deliver(packet);
+            }
+        }
+    }
+
+    fn deliver(&mut self, packet: Packet) {
+        if let Some(&i) = self.addresses.get(&packet.dest) {
+            self.nodes[i].inbox.extend(&[packet.x, packet.y]);
+        }
+    }
+
+    // Runs until a round in which nothing was sent or received. A packet held
+    // by the NAT has been passed on by then.
+    pub fn run_until_idle(&mut self) -> Result<(), NetworkError> {
+        while !self.round()? {}
+        Ok(())
+    }
+
+    // The first packet the NAT passes on with the same y as the one before it.
+    // Going idle with nothing for the NAT to pass on ends it.
+    pub fn run_until_nat_repeats(&mut self) -> Result<Packet, NetworkError> {
+        loop {
+            let idle = self.round()?;
+            if self.all_halted() {
+                return Err(NetworkError::Halted);
+            }
+            if idle && self.nat_held.is_none() {
+                return Err(NetworkError::Idle);
+            }
+            if let [.., previous, last] = self.nat_sent[..] {
+                if previous.y == last.y {
+                    return Ok(last);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::asm::assemble;
+    use crate::intcode::parse_program;
+
+    #[test]
+    fn amplifier_ring() {
+        // day 7's second example
+        let program = parse_program(
+            "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5",
+        );
+        let names = ["A", "B", "C", "D", "E"];
+        let mut builder = NetworkBuilder::new();
+        for (i, name) in names.iter().enumerate() {
+            builder
+                .node(name, &program, vec![9 - i as i64])
+                .edge(name, names[(i + 1) % 5]);
+        }
+        let mut network = builder.build().unwrap();
+        network.send("A", 0).unwrap();
+        network.run_until_idle().unwrap();
+        assert!(network.all_halted());
+        assert_eq!(network.outputs("E").unwrap().last(), Some(&139629729));
+    }
+
+    #[test]
+    fn nat_wakes_idle_network() {
+        // Forwards every packet it receives to the NAT
+        let relay = assemble(
+            "
+                    IN   [x]
+            loop:   IN   [x]
+                    EQ   [x], #-1, [t]
+                    JT   [t], #loop
+                    IN   [y]
+                    OUT  #255
+                    OUT  [x]
+                    OUT  [y]
+                    JT   #1, #loop
+            x:      .data 0
+            y:      .data 0
+            t:      .data 0
+            ",
+        )
+        .unwrap();
+        let start =
+            assemble("IN [a]\nOUT #0\nOUT #5\nOUT #9\nidle: IN [a]\nJT #1, #idle\na: .data 0")
+                .unwrap();
+
+        let mut network = NetworkBuilder::new()
+            .addressed_node("relay", &relay, 0)
+            .addressed_node("start", &start, 1)
+            .nat(255)
+            .build()
+            .unwrap();
+        let packet = network.run_until_nat_repeats().unwrap();
+        assert_eq!((packet.x, packet.y), (5, 9));
+        assert_eq!(network.nat_sent().len(), 2);
+        assert_eq!(
+            network.packets()[0],
+            Packet {
+                from: 1,
+                dest: 0,
+                x: 5,
+                y: 9
+            }
+        );
+    }
+
+    #[test]
+    fn idle_without_nat_packets() {
+        // Only ever reads
+        let listener = assemble("IN [a]\nloop: IN [a]\nJT #1, #loop\na: .data 0").unwrap();
+        let mut network = NetworkBuilder::new()
+            .addressed_node("listener", &listener, 0)
+            .nat(255)
+            .build()
+            .unwrap();
+        assert_eq!(network.run_until_nat_repeats(), Err(NetworkError::Idle));
+
+        let mut network = NetworkBuilder::new()
+            .addressed_node("listener", &listener, 0)
+            .build()
+            .unwrap();
+        assert_eq!(network.run_until_nat_repeats(), Err(NetworkError::Idle));
+    }
+
+    #[test]
+    fn rejects_unknown_edges() {
+        let result = NetworkBuilder::new()
+            .node("a", &[99], vec![])
+            .edge("a", "b")
+            .build();
+        assert_eq!(
+            result.err(),
+            Some(NetworkError::UnknownNode("b".to_string()))
+        );
+    }
+}
@@ -0,0 +1,138 @@
+// Runs a group of machines on their own threads, connected by channels.
+//
+// - `Chain`: each machine feeds the next, the last one's output is the result.
+// - `Ring`: a chain whose last machine also feeds the first.
+// - `FanOut`: the first machine's outputs go to every other machine, and each
+//   of those reports a result.
+//
+// Every machine starts with its own initial inputs (a day 7 phase setting),
+// and the first one is then sent the seed value.
+
+use super::io::OutputSink;
+use super::{get_computer, IntcodeError};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Topology {
+    Chain,
+    Ring,
+    FanOut,
+}
+
+impl Topology {
+    fn targets(self, node: usize, count: usize) -> Vec<usize> {
+        match self {
+            Topology::Chain if node + 1 < count => vec![node + 1],
+            Topology::Ring => vec![(node + 1) % count],
+            Topology::FanOut if node == 0 => (1..count).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn reports(self, node: usize, count: usize) -> bool {
+        match self {
+            Topology::Chain | Topology::Ring => node + 1 == count,
+            Topology::FanOut => node > 0 || count == 1,
+        }
+    }
+}
+
+// Forwards each value to every downstream machine and remembers the last one
+struct Fanout {
+    senders: Vec<Sender<i64>>,
+    last: Option<i64>,
+}
+
+impl OutputSink for Fanout {
+    fn emit(&mut self, value: i64) {
+        self.last = Some(value);
+        for sender in self.senders.iter_mut() {
+            sender.emit(value);
+        }
+    }
+}
+
+// The last value each reporting machine produced, in machine order. A
+// machine that never produced anything reports `None`.
+pub fn run_pipeline(
+    program: &[i64],
+    initial_inputs: &[Vec<i64>],
+    seed: i64,
+    topology: Topology,
+) -> Result<Vec<Option<i64>>, IntcodeError> {
+    let count = initial_inputs.len();
+    let (senders, receivers): (Vec<Sender<i64>>, Vec<Receiver<i64>>) =
+        (0..count).map(|_| channel()).unzip();
+    if let Some(first) = senders.first() {
+        first.send(seed).unwrap();
+    }
+
+    let handles: Vec<_> = receivers
+        .into_iter()
+        .zip(initial_inputs)
+        .enumerate()
+        .map(|(node, (mut input, initial))| {
+            let mut computer = get_computer(program, initial.clone());
+            let mut output = Fanout {
+                senders: topology
+                    .targets(node, count)
+                    .into_iter()
+                    .map(|target| senders[target].clone())
+                    .collect(),
+                last: None,
+            };
+            thread::spawn(move || {
+                computer.run_with_io(&mut input, &mut output)?;
+                Ok(output.last)
+            })
+        })
+        .collect();
+    // Machines see a hang-up once everything upstream of them is finished
+    drop(senders);
+
+    let mut results = Vec::new();
+    for (node, handle) in handles.into_iter().enumerate() {
+        let last = handle.join().expect("intcode machine thread panicked")?;
+        if topology.reports(node, count) {
+            results.push(last);
+        }
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::parse_program;
+
+    // Adds its first input to every later one until it reads a zero
+    const ADDER: &str = "3,20,3,21,1006,21,19,1,20,21,21,4,21,1105,1,2,0,0,0,99,0,0";
+
+    fn run(topology: Topology, phases: &[i64], seed: i64) -> Vec<Option<i64>> {
+        let phases: Vec<Vec<i64>> = phases.iter().map(|&p| vec![p]).collect();
+        run_pipeline(&parse_program(ADDER), &phases, seed, topology).unwrap()
+    }
+
+    #[test]
+    fn chain_and_fan_out() {
+        assert_eq!(run(Topology::Chain, &[1, 10, 100], 5), vec![Some(116)]);
+        assert_eq!(
+            run(Topology::FanOut, &[1, 10, 100], 5),
+            vec![Some(16), Some(106)]
+        );
+    }
+
+    #[test]
+    fn ring_feeds_back() {
+        // day 7's second example
+        let program = parse_program(
+            "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5",
+        );
+        let phases: Vec<Vec<i64>> = [9, 8, 7, 6, 5].iter().map(|&p| vec![p]).collect();
+        assert_eq!(
+            run_pipeline(&program, &phases, 0, Topology::Ring),
+            Ok(vec![Some(139629729)])
+        );
+    }
+}
@@ -0,0 +1,238 @@
+// Execution counts for finding where a program spends its time. A profile
+// counts every executed instruction by address and by opcode, leaving out
+// those that fault, and every backward jump taken, which is taken to close a
+// loop over the addresses between its target and itself. Jumps report
+// themselves, so ip moving back for any other reason, like stepping back
+// through history or restoring a snapshot, is not mistaken for a loop.
+
+use super::memory::Memory;
+use super::IntCodeComputer;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    pub cycles: u64,
+    pub address_hits: BTreeMap<usize, u64>,
+    pub opcode_hits: BTreeMap<&'static str, u64>,
+    backward_jumps: BTreeMap<(usize, usize), u64>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct HotLoop {
+    pub start: usize,
+    pub end: usize,
+    pub iterations: u64,
+    // Instructions executed inside start..=end, nested loops included
+    pub instructions: u64,
+}
+
+impl Profile {
+    pub(super) fn record(&mut self, ip: usize, mnemonic: &'static str) {
+        self.cycles += 1;
+        *self.address_hits.entry(ip).or_insert(0) += 1;
+        *self.opcode_hits.entry(mnemonic).or_insert(0) += 1;
+    }
+
+    pub(super) fn record_jump(&mut self, from: usize, to: usize) {
+        if to <= from {
+            *self.backward_jumps.entry((to, from)).or_insert(0) += 1;
+        }
+    }
+
+    // Loops from the outermost in, each followed by those nested in it
+    pub fn hot_loops(&self) -> Vec<HotLoop> {
+        let mut loops: Vec<HotLoop> = self
+            .backward_jumps
+            .iter()
+            .map(|(&(start, end), &iterations)| HotLoop {
+                start,
+                end,
+                iterations,
+                instructions: self.address_hits.range(start..=end).map(|(_, n)| n).sum(),
+            })
+            .collect();
+        loops.sort_by_key(|l| (l.start, std::cmp::Reverse(l.end)));
+        loops
+    }
+
+    fn share(&self, count: u64) -> f64 {
+        100.0 * count as f64 / self.cycles.max(1) as f64
+    }
+
+    // `describe` names the instruction at an address, e.g. its disassembly
+    pub fn report(&self, top: usize, describe: impl Fn(usize) -> String) -> String {
+        let mut out = String::new();
+        writeln!(out, "cycles: {}", self.cycles).unwrap();
+
+        writeln!(out, "\n{:<8}{:>12}{:>8}", "opcode", "count", "share").unwrap();
+        let mut opcodes: Vec<_> = self.opcode_hits.iter().collect();
+        opcodes.sort_by_key(|&(_, &count)| std::cmp::Reverse(count));
+        for (mnemonic, &count) in opcodes {
+            writeln!(
+                out,
+                "{:<8}{:>12}{:>7.1}%",
+                mnemonic,
+                count,
+                self.share(count)
+            )
+            .unwrap();
+        }
+
+        writeln!(
+            out,
+            "\n{:<8}{:>12}{:>8}  instruction",
+            "address", "count", "share"
+        )
+        .unwrap();
+        let mut addresses: Vec<(usize, u64)> = self
+            .address_hits
+            .iter()
+            .map(|(&addr, &count)| (addr, count))
+            .collect();
+        addresses.sort_by_key(|&(addr, count)| (std::cmp::Reverse(count), addr));
+        for (addr, count) in addresses.into_iter().take(top) {
+            writeln!(
+                out,
+                "{:<8}{:>12}{:>7.1}%  {}",
+                addr,
+                count,
+                self.share(count),
+                describe(addr)
+            )
+            .unwrap();
+        }
+
+        let loops = self.hot_loops();
+        if !loops.is_empty() {
+            writeln!(out, "\nloops").unwrap();
+        }
+        let mut enclosing: Vec<&HotLoop> = Vec::new();
+        for l in &loops {
+            while enclosing.last().is_some_and(|outer| outer.end < l.end) {
+                enclosing.pop();
+            }
+            writeln!(
+                out,
+                "{:>6.1}%  {}{}..{} ({} iterations)",
+                self.share(l.instructions),
+                "  ".repeat(enclosing.len()),
+                l.start,
+                l.end,
+                l.iterations
+            )
+            .unwrap();
+            enclosing.push(l);
+        }
+        out
+    }
+}
+
+impl<M: Memory> IntCodeComputer<M> {
+    pub fn enable_profile(&mut self) -> &mut Self {
+        self.profile.get_or_insert_with(Profile::default);
+        self
+    }
+
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    // Memory grows on demand, so its end is the highest address the program
+    // has written to, or the end of the program itself
+    pub fn memory_high_water_mark(&self) -> usize {
+        let runs = self.memory.runs();
+        runs.last().map_or(0, |(start, run)| start + run.len())
+    }
+
+    // Report on the profile so far, disassembling the hottest addresses
+    pub fn profile_report(&self, top: usize) -> Option<String> {
+        let describe = |addr| match self.instruction_at(addr) {
+            Ok(inst) => inst.to_string(),
+            Err(_) => "??".to_string(),
+        };
+        let report = self.profile.as_ref()?.report(top, describe);
+        Some(format!(
+            "memory high-water mark: {} words\n{}",
+            self.memory_high_water_mark(),
+            report
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intcode::asm::assemble;
+    use crate::intcode::memory::SparseMemory;
+    use crate::intcode::{get_computer, parse_program, IntCodeComputer};
+
+    #[test]
+    fn counts_nested_loops() {
+        // Three passes of an outer loop, each running the inner loop twice
+        let source = "
+                    ADD  #3, #0, [i]
+            outer:  ADD  #2, #0, [j]
+            inner:  ADD  [j], #-1, [j]
+                    JT   [j], #inner
+                    ADD  [i], #-1, [i]
+                    JT   [i], #outer
+                    HLT
+            i:      .data 0
+            j:      .data 0
+        ";
+        let mut computer = get_computer(&assemble(source).unwrap(), vec![]);
+        computer.enable_profile();
+        computer.run_till_halt().unwrap();
+
+        let profile = computer.profile().unwrap();
+        assert_eq!(profile.cycles, 1 + 3 * (1 + 2 * 2 + 2) + 1);
+        assert_eq!(profile.opcode_hits["JT"], 3 * 2 + 3);
+
+        let loops: Vec<_> = profile
+            .hot_loops()
+            .iter()
+            .map(|l| (l.start, l.end, l.iterations))
+            .collect();
+        assert_eq!(loops, vec![(4, 19, 2), (8, 12, 3)]);
+
+        assert_eq!(computer.memory_high_water_mark(), 25);
+        let report = computer.profile_report(3).unwrap();
+        assert!(
+            report.contains("\n  91.3%  4..19 (2 iterations)\n  52.2%    8..12 (3 iterations)\n")
+        );
+    }
+
+    #[test]
+    fn only_jumps_close_loops() {
+        let program = parse_program("1101,1,1,9,1101,2,2,9,99,0");
+        let mut computer = get_computer(&program, vec![]);
+        computer.enable_profile().enable_history(10);
+        computer.tick().unwrap();
+        computer.tick().unwrap();
+        computer.step_back_n(2);
+        computer.run_till_halt().unwrap();
+        assert_eq!(computer.profile().unwrap().cycles, 5);
+        assert_eq!(computer.profile().unwrap().hot_loops(), vec![]);
+    }
+
+    #[test]
+    fn faults_and_far_addresses() {
+        // The second ADD writes to -1
+        let program = parse_program("1101,1,1,9,1101,1,1,-1,99,0");
+        let mut computer = get_computer(&program, vec![]);
+        computer.enable_profile();
+        assert!(computer.run().is_err());
+        assert_eq!(computer.profile().unwrap().cycles, 1);
+
+        // Jumps to 2^40 and halts there
+        let mut computer = IntCodeComputer::<SparseMemory>::new();
+        computer.load_memory(parse_program("1105,1,1099511627776"));
+        computer.store_value_at_pos(1 << 40, 99);
+        computer.enable_profile().run().unwrap();
+        let hits = &computer.profile().unwrap().address_hits;
+        assert_eq!(
+            hits.iter().collect::<Vec<_>>(),
+            vec![(&0, &1), (&(1 << 40), &1)]
+        );
+    }
+}
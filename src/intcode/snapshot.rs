@@ -0,0 +1,210 @@
+// Complete machine state, detached from any debugging aids. Snapshots are
+// saved as one `key value` line per field, plus a `memory <start> <words>`
+// line for each allocated run of memory:
+//
+//     ip 4
+//     rb 0
+//     input 5,6
+//     output -
+//     memory 0 3,9,1001,9,1,9,4,9,99,0
+//
+// Taking a snapshot clones the memory backend, which for `CowMemory` only
+// shares its pages.
+
+use super::memory::{DenseMemory, Memory};
+use super::word::Word;
+use super::IntCodeComputer;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot<M: Memory = DenseMemory> {
+    pub memory: M,
+    pub ip: usize,
+    pub relative_base: i64,
+    pub input: Vec<M::Word>,
+    pub output: Option<M::Word>,
+}
+
+fn join<W: Word>(values: &[W]) -> String {
+    values
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl<M: Memory> Snapshot<M> {
+    pub fn to_text(&self) -> String {
+        let output = self
+            .output
+            .as_ref()
+            .map_or("-".to_string(), |o| o.to_string());
+        let mut text = format!(
+            "ip {}\nrb {}\ninput {}\noutput {}\n",
+            self.ip,
+            self.relative_base,
+            join(&self.input),
+            output
+        );
+        for (start, run) in self.memory.runs() {
+            text += &format!("memory {} {}\n", start, join(run));
+        }
+        text
+    }
+
+    pub fn from_text(text: &str) -> io::Result<Snapshot<M>> {
+        let mut snapshot = Snapshot {
+            memory: M::default(),
+            ip: 0,
+            relative_base: 0,
+            input: Vec::new(),
+            output: None,
+        };
+        let number = |s: &str| {
+            s.parse::<M::Word>()
+                .map_err(|_| invalid(format!("bad number `{}`", s)))
+        };
+        let list = |s: &str| -> io::Result<Vec<M::Word>> {
+            s.split(',').filter(|w| !w.is_empty()).map(number).collect()
+        };
+
+        for line in text.lines().filter(|l| !l.trim().is_empty()) {
+            let mut parts = line.trim().splitn(2, ' ');
+            let key = parts.next().unwrap();
+            let value = parts.next().unwrap_or("").trim();
+            match key {
+                "ip" => {
+                    snapshot.ip = value
+                        .parse::<usize>()
+                        .map_err(|_| invalid(format!("bad ip `{}`", value)))?
+                }
+                "rb" => {
+                    snapshot.relative_base = value
+                        .parse::<i64>()
+                        .map_err(|_| invalid(format!("bad relative base `{}`", value)))?
+                }
+                "input" => snapshot.input = list(value)?,
+                "output" if value == "-" => snapshot.output = None,
+                "output" => snapshot.output = Some(number(value)?),
+                "memory" => {
+                    let mut parts = value.splitn(2, ' ');
+                    let start = parts.next().unwrap();
+                    let start = start
+                        .parse::<usize>()
+                        .map_err(|_| invalid(format!("bad address `{}`", start)))?;
+                    let words = list(parts.next().unwrap_or(""))?;
+                    match start.checked_add(words.len()) {
+                        Some(end) if end <= M::ADDRESS_LIMIT => {}
+                        _ => return Err(invalid(format!("address {} out of range", start))),
+                    }
+                    for (i, word) in words.into_iter().enumerate() {
+                        snapshot.memory.set(start + i, word);
+                    }
+                }
+                _ => return Err(invalid(format!("unknown snapshot field `{}`", key))),
+            }
+        }
+        Ok(snapshot)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_text())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Snapshot<M>> {
+        Snapshot::from_text(&fs::read_to_string(path)?)
+    }
+}
+
+impl<M: Memory> IntCodeComputer<M> {
+    pub fn snapshot(&self) -> Snapshot<M> {
+        Snapshot {
+            memory: self.memory.clone(),
+            ip: self.ip,
+            relative_base: self.relative_base_offset,
+            input: self.input.iter().cloned().collect(),
+            output: self.output.clone(),
+        }
+    }
+
+    // The undo log describes the state being replaced, so it is cleared
+    pub fn restore(&mut self, snapshot: &Snapshot<M>) -> &mut Self {
+        self.memory = snapshot.memory.clone();
+        if let Some(cache) = self.cache.as_mut() {
+            cache.clear();
+        }
+        self.ip = snapshot.ip;
+        self.relative_base_offset = snapshot.relative_base;
+        self.input = snapshot.input.iter().cloned().collect();
+        self.output = snapshot.output.clone();
+        self.halted = false;
+        if let Some(history) = self.history.as_mut() {
+            history.clear();
+        }
+        self
+    }
+
+    pub fn from_snapshot(snapshot: &Snapshot<M>) -> Self {
+        let mut computer = IntCodeComputer::new();
+        computer.restore(snapshot);
+        computer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::memory::CowMemory;
+    use crate::intcode::{get_computer, parse_program, Signal};
+
+    #[test]
+    fn restores_mid_run() {
+        let program = parse_program("3,9,1001,9,1,9,4,9,99,0");
+        let mut computer = get_computer(&program, vec![]);
+        assert_eq!(computer.run(), Ok(Signal::NeedsInput));
+        let snapshot = computer.snapshot();
+
+        let text = snapshot.to_text();
+        assert_eq!(
+            text,
+            "ip 0\nrb 0\ninput \noutput -\nmemory 0 3,9,1001,9,1,9,4,9,99,0\n"
+        );
+        assert_eq!(Snapshot::from_text(&text).unwrap(), snapshot);
+
+        for input in 1..4 {
+            computer.restore(&snapshot).feed_input(input);
+            assert_eq!(computer.run_till_halt(), Ok(vec![input + 1]));
+        }
+    }
+
+    #[test]
+    fn clones_fork_independently() {
+        let mut computer = get_computer(&parse_program("3,9,1001,9,1,9,4,9,99,0"), vec![]);
+        computer.run().unwrap();
+        let mut fork = computer.clone();
+        computer.feed_input(1);
+        fork.feed_input(10);
+        assert_eq!(computer.run_till_halt(), Ok(vec![2]));
+        assert_eq!(fork.run_till_halt(), Ok(vec![11]));
+    }
+
+    #[test]
+    fn sparse_snapshots_round_trip() {
+        let mut computer = IntCodeComputer::<CowMemory>::new();
+        computer
+            .load_memory(parse_program("3,1000000,99"))
+            .set_input(vec![5]);
+        computer.run_till_halt().unwrap();
+
+        let snapshot = computer.snapshot();
+        let restored = Snapshot::<CowMemory>::from_text(&snapshot.to_text()).unwrap();
+        assert_eq!(restored, snapshot);
+        assert_eq!(restored.memory.get(1_000_000), 5);
+    }
+}
@@ -0,0 +1,560 @@
+// Symbolic execution, where chosen memory words are named variables instead
+// of numbers. ADD and MUL build expression trees over them; everything else
+// needs concrete values, so addresses written to, comparisons, jumps and
+// relative base changes all have to work out to numbers. Reading through an
+// address that isn't known gives an opaque load, which is fine as long as it
+// gets overwritten before it reaches the result.
+//
+// A result that only involves ADD and MUL is a polynomial in the variables,
+// which `solve` can search far faster than running the program for every
+// combination of values.
+
+use super::memory::DENSE_LIMIT;
+use super::{decode, Instruction, IntcodeError, Parameter};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::rc::Rc;
+
+// Symbolic runs are meant for short, mostly straight-line programs
+const MAX_STEPS: usize = 1_000_000;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Expr {
+    Const(i64),
+    Var(String),
+    Add(Rc<Expr>, Rc<Expr>),
+    Mul(Rc<Expr>, Rc<Expr>),
+    // The word at an address that depends on a variable
+    Load(Rc<Expr>),
+}
+
+impl Expr {
+    pub fn constant(&self) -> Option<i64> {
+        match self {
+            Expr::Const(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    // None once the expression involves a load
+    pub fn to_poly(&self) -> Option<Poly> {
+        match self {
+            Expr::Const(value) => Some(Poly::constant(*value)),
+            Expr::Var(name) => Some(Poly::var(name)),
+            Expr::Add(a, b) => a.to_poly()?.add(&b.to_poly()?),
+            Expr::Mul(a, b) => a.to_poly()?.mul(&b.to_poly()?),
+            Expr::Load(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Const(value) => write!(f, "{}", value),
+            Expr::Var(name) => write!(f, "{}", name),
+            Expr::Add(a, b) => write!(f, "({} + {})", a, b),
+            Expr::Mul(a, b) => write!(f, "{} * {}", a, b),
+            Expr::Load(addr) => write!(f, "mem[{}]", addr),
+        }
+    }
+}
+
+// Constants are folded as the trees are built, so an expression without
+// variables is always a single `Const`
+fn sum(a: Rc<Expr>, b: Rc<Expr>) -> Option<Rc<Expr>> {
+    Some(match (a.constant(), b.constant()) {
+        (Some(x), Some(y)) => Rc::new(Expr::Const(x.checked_add(y)?)),
+        (Some(0), _) => b,
+        (_, Some(0)) => a,
+        _ => Rc::new(Expr::Add(a, b)),
+    })
+}
+
+fn product(a: Rc<Expr>, b: Rc<Expr>) -> Option<Rc<Expr>> {
+    Some(match (a.constant(), b.constant()) {
+        (Some(x), Some(y)) => Rc::new(Expr::Const(x.checked_mul(y)?)),
+        (Some(0), _) | (_, Some(0)) => Rc::new(Expr::Const(0)),
+        (Some(1), _) => b,
+        (_, Some(1)) => a,
+        _ => Rc::new(Expr::Mul(a, b)),
+    })
+}
+
+// Terms keyed by their sorted variable names, so `x^2*y` is `["x", "x", "y"]`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Poly {
+    terms: BTreeMap<Vec<String>, i64>,
+}
+
+impl Poly {
+    pub fn constant(value: i64) -> Poly {
+        let mut poly = Poly::default();
+        if value != 0 {
+            poly.terms.insert(Vec::new(), value);
+        }
+        poly
+    }
+
+    pub fn var(name: &str) -> Poly {
+        let mut poly = Poly::default();
+        poly.terms.insert(vec![name.to_string()], 1);
+        poly
+    }
+
+    fn insert(&mut self, monomial: Vec<String>, coefficient: i64) -> Option<()> {
+        let sum = self
+            .terms
+            .get(&monomial)
+            .map_or(Some(coefficient), |c| c.checked_add(coefficient))?;
+        match sum {
+            0 => self.terms.remove(&monomial),
+            sum => self.terms.insert(monomial, sum),
+        };
+        Some(())
+    }
+
+    pub fn add(&self, other: &Poly) -> Option<Poly> {
+        let mut poly = self.clone();
+        for (monomial, &c) in &other.terms {
+            poly.insert(monomial.clone(), c)?;
+        }
+        Some(poly)
+    }
+
+    pub fn mul(&self, other: &Poly) -> Option<Poly> {
+        let mut poly = Poly::default();
+        for (a, &x) in &self.terms {
+            for (b, &y) in &other.terms {
+                let mut monomial: Vec<String> = a.iter().chain(b).cloned().collect();
+                monomial.sort();
+                poly.insert(monomial, x.checked_mul(y)?)?;
+            }
+        }
+        Some(poly)
+    }
+
+    // Replaces a variable with a value
+    pub fn substitute(&self, name: &str, value: i64) -> Option<Poly> {
+        let mut poly = Poly::default();
+        for (monomial, &c) in &self.terms {
+            let mut c = c;
+            let mut rest = Vec::new();
+            for var in monomial {
+                match var == name {
+                    true => c = c.checked_mul(value)?,
+                    false => rest.push(var.clone()),
+                }
+            }
+            poly.insert(rest, c)?;
+        }
+        Some(poly)
+    }
+
+    pub fn as_constant(&self) -> Option<i64> {
+        match self.terms.len() {
+            0 => Some(0),
+            1 => self.terms.get(&Vec::new()).copied(),
+            _ => None,
+        }
+    }
+
+    pub fn variables(&self) -> BTreeSet<&str> {
+        self.terms.keys().flatten().map(String::as_str).collect()
+    }
+
+    pub fn degree_in(&self, name: &str) -> usize {
+        self.terms
+            .keys()
+            .map(|m| m.iter().filter(|v| *v == name).count())
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn coefficient(&self, monomial: &[&str]) -> i64 {
+        self.terms
+            .iter()
+            .find(|(m, _)| m.iter().map(String::as_str).eq(monomial.iter().copied()))
+            .map_or(0, |(_, &c)| c)
+    }
+}
+
+// Highest degree first, constant last, e.g. `3*x^2*y - x + 7`
+impl fmt::Display for Poly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.terms.is_empty() {
+            return write!(f, "0");
+        }
+        let mut terms: Vec<_> = self.terms.iter().collect();
+        terms.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then(a.cmp(b)));
+        for (i, (monomial, &c)) in terms.into_iter().enumerate() {
+            let mut factors = Vec::new();
+            let mut rest = &monomial[..];
+            while let Some(var) = rest.first() {
+                let power = rest.iter().take_while(|v| *v == var).count();
+                factors.push(match power {
+                    1 => var.clone(),
+                    _ => format!("{}^{}", var, power),
+                });
+                rest = &rest[power..];
+            }
+            let magnitude = c.unsigned_abs();
+            let mut term = match (magnitude, factors.is_empty()) {
+                (1, false) => String::new(),
+                (_, false) => format!("{}*", magnitude),
+                (_, true) => magnitude.to_string(),
+            };
+            term += &factors.join("*");
+            match (i, c < 0) {
+                (0, true) => write!(f, "-{}", term)?,
+                (0, false) => write!(f, "{}", term)?,
+                (_, true) => write!(f, " - {}", term)?,
+                (_, false) => write!(f, " + {}", term)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// The first `x` in `xs` and `y` in `ys`, trying `x` in the outer loop, for
+// which `poly` equals `target`. For each `x` the polynomial is solved for `y`
+// directly when it's at most linear in `y`, and `y` is searched otherwise.
+pub fn solve(
+    poly: &Poly,
+    target: i64,
+    (x, xs): (&str, RangeInclusive<i64>),
+    (y, ys): (&str, RangeInclusive<i64>),
+) -> Option<(i64, i64)> {
+    if poly.variables().iter().any(|v| *v != x && *v != y) {
+        return None;
+    }
+    for xv in xs {
+        let rest = match poly.substitute(x, xv) {
+            Some(rest) => rest,
+            None => continue,
+        };
+        if rest.degree_in(y) > 1 {
+            let hit = ys
+                .clone()
+                .find(|&yv| rest.substitute(y, yv).and_then(|p| p.as_constant()) == Some(target));
+            if let Some(yv) = hit {
+                return Some((xv, yv));
+            }
+            continue;
+        }
+        let (slope, offset) = (rest.coefficient(&[y]), rest.coefficient(&[]));
+        let diff = match target.checked_sub(offset) {
+            Some(diff) => diff,
+            None => continue,
+        };
+        let yv = match slope {
+            0 if diff == 0 && !ys.is_empty() => *ys.start(),
+            0 => continue,
+            // i64::MIN / -1 has no i64 answer
+            _ => match (diff.checked_rem(slope), diff.checked_div(slope)) {
+                (Some(0), Some(yv)) => yv,
+                _ => continue,
+            },
+        };
+        if ys.contains(&yv) {
+            return Some((xv, yv));
+        }
+    }
+    None
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SymbolicError {
+    Intcode(IntcodeError),
+    // An address, condition or comparison that depends on a variable
+    NotConcrete { ip: usize, opcode: i64 },
+    NeedsInput { ip: usize },
+    TooManySteps,
+}
+
+impl fmt::Display for SymbolicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SymbolicError::Intcode(e) => write!(f, "{}", e),
+            SymbolicError::NotConcrete { ip, opcode } => write!(
+                f,
+                "opcode {} at ip {} needs a value that depends on a variable",
+                opcode, ip
+            ),
+            SymbolicError::NeedsInput { ip } => write!(f, "out of input at ip {}", ip),
+            SymbolicError::TooManySteps => {
+                write!(f, "gave up after {} instructions", MAX_STEPS)
+            }
+        }
+    }
+}
+
+impl Error for SymbolicError {}
+
+impl From<IntcodeError> for SymbolicError {
+    fn from(e: IntcodeError) -> Self {
+        SymbolicError::Intcode(e)
+    }
+}
+
+pub struct SymbolicComputer {
+    memory: Vec<Rc<Expr>>,
+    input: VecDeque<i64>,
+    outputs: Vec<Rc<Expr>>,
+    ip: usize,
+    relative_base: i64,
+}
+
+impl SymbolicComputer {
+    pub fn new(program: &[i64]) -> Self {
+        SymbolicComputer {
+            memory: program.iter().map(|&w| Rc::new(Expr::Const(w))).collect(),
+            input: VecDeque::new(),
+            outputs: Vec::new(),
+            ip: 0,
+            relative_base: 0,
+        }
+    }
+
+    pub fn symbolize(&mut self, addr: usize, name: &str) -> &mut Self {
+        self.store(addr, Rc::new(Expr::Var(name.to_string())));
+        self
+    }
+
+    pub fn feed_input(&mut self, value: i64) {
+        self.input.push_back(value);
+    }
+
+    pub fn value_at(&self, addr: usize) -> Rc<Expr> {
+        match self.memory.get(addr) {
+            Some(value) => value.clone(),
+            None => Rc::new(Expr::Const(0)),
+        }
+    }
+
+    pub fn outputs(&self) -> &[Rc<Expr>] {
+        &self.outputs
+    }
+
+    fn store(&mut self, addr: usize, value: Rc<Expr>) {
+        if self.memory.len() <= addr {
+            self.memory.resize(addr + 1, Rc::new(Expr::Const(0)));
+        }
+        self.memory[addr] = value;
+    }
+
+    // Runs until the program halts
+    pub fn run(&mut self) -> Result<(), SymbolicError> {
+        for _ in 0..MAX_STEPS {
+            if self.step()? {
+                return Ok(());
+            }
+        }
+        Err(SymbolicError::TooManySteps)
+    }
+
+    // Returns whether the program halted
+    fn step(&mut self) -> Result<bool, SymbolicError> {
+        let ip = self.ip;
+        let opcode = self
+            .value_at(ip)
+            .constant()
+            .ok_or(SymbolicError::NotConcrete { ip, opcode: 0 })?;
+        let overflow = || IntcodeError::Overflow { ip, opcode };
+        let address = |addr: i64| match addr {
+            addr if addr < 0 => Err(IntcodeError::NegativeAddress {
+                ip,
+                opcode,
+                address: addr,
+            }),
+            addr => Ok(addr as usize),
+        };
+
+        // Decoded for its shape only, operands come from the symbolic words
+        let inst = decode(|i| self.value_at(i).constant().unwrap_or(0), ip)
+            .map_err(|fault| fault.at(ip, opcode))?;
+        let params: Vec<(Parameter, Rc<Expr>)> = inst
+            .params()
+            .into_iter()
+            .enumerate()
+            .map(|(n, p)| (p, self.value_at(ip + n + 1)))
+            .collect();
+        let read = |n: usize| -> Result<Rc<Expr>, SymbolicError> {
+            let (param, word) = &params[n];
+            let addr = match param {
+                Parameter::Immediate(_) => return Ok(word.clone()),
+                Parameter::Position(_) => word.clone(),
+                Parameter::Relative(_) => {
+                    sum(Rc::new(Expr::Const(self.relative_base)), word.clone())
+                        .ok_or_else(overflow)?
+                }
+            };
+            match addr.constant() {
+                Some(addr) => Ok(self.value_at(address(addr)?)),
+                None => Ok(Rc::new(Expr::Load(addr))),
+            }
+        };
+        let concrete = |n: usize| -> Result<i64, SymbolicError> {
+            read(n)?
+                .constant()
+                .ok_or(SymbolicError::NotConcrete { ip, opcode })
+        };
+        let target = |n: usize| -> Result<usize, SymbolicError> {
+            let (param, word) = &params[n];
+            let word = word
+                .constant()
+                .ok_or(SymbolicError::NotConcrete { ip, opcode })?;
+            let addr = match param {
+                Parameter::Immediate(_) => {
+                    return Err(IntcodeError::WriteToImmediate {
+                        ip,
+                        opcode,
+                        operand: word,
+                    }
+                    .into())
+                }
+                Parameter::Position(_) => word,
+                Parameter::Relative(_) => {
+                    self.relative_base.checked_add(word).ok_or_else(overflow)?
+                }
+            };
+            // Memory is dense, with the same limit as the VM's
+            match address(addr)? {
+                addr if addr >= DENSE_LIMIT => Err(IntcodeError::AddressTooLarge {
+                    ip,
+                    opcode,
+                    address: addr,
+                }
+                .into()),
+                addr => Ok(addr),
+            }
+        };
+
+        let mut next = ip + inst.size();
+        match inst {
+            Instruction::Add(_) => {
+                let value = sum(read(0)?, read(1)?).ok_or_else(overflow)?;
+                let dst = target(2)?;
+                self.store(dst, value);
+            }
+            Instruction::Mul(_) => {
+                let value = product(read(0)?, read(1)?).ok_or_else(overflow)?;
+                let dst = target(2)?;
+                self.store(dst, value);
+            }
+            Instruction::LessThan(_) | Instruction::Equals(_) => {
+                let (a, b) = (concrete(0)?, concrete(1)?);
+                let value = match inst {
+                    Instruction::LessThan(_) => a < b,
+                    _ => a == b,
+                };
+                let dst = target(2)?;
+                self.store(dst, Rc::new(Expr::Const(value as i64)));
+            }
+            Instruction::Input(_) => {
+                let dst = target(0)?;
+                let value = self
+                    .input
+                    .pop_front()
+                    .ok_or(SymbolicError::NeedsInput { ip })?;
+                self.store(dst, Rc::new(Expr::Const(value)));
+            }
+            Instruction::Output(_) => {
+                let value = read(0)?;
+                self.outputs.push(value);
+            }
+            Instruction::JumpIfTrue(_) | Instruction::JumpIfFalse(_) => {
+                let jump_if = matches!(inst, Instruction::JumpIfTrue(_));
+                if (concrete(0)? != 0) == jump_if {
+                    next = address(concrete(1)?)?;
+                }
+            }
+            Instruction::RelativeBaseOffset(_) => {
+                let offset = concrete(0)?;
+                self.relative_base = self
+                    .relative_base
+                    .checked_add(offset)
+                    .ok_or_else(overflow)?;
+            }
+            Instruction::Halt => {
+                self.ip = next;
+                return Ok(true);
+            }
+        }
+        self.ip = next;
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::parse_program;
+
+    #[test]
+    fn builds_polynomials() {
+        // Like day 2, the first instruction reads through the variables and
+        // its result is overwritten: mem[0] = (x + y) * x
+        let program = parse_program("1,0,0,3,1,1,2,0,2,0,1,0,99");
+        let mut computer = SymbolicComputer::new(&program);
+        computer.symbolize(1, "x").symbolize(2, "y");
+        computer.run().unwrap();
+        let poly = computer.value_at(0).to_poly().unwrap();
+        assert_eq!(poly.to_string(), "x^2 + x*y");
+
+        assert_eq!(solve(&poly, 12, ("x", 0..=5), ("y", 0..=5)), Some((2, 4)));
+        assert_eq!(solve(&poly, 13, ("x", 0..=5), ("y", 0..=5)), None);
+    }
+
+    #[test]
+    fn solves_linear_and_nonlinear() {
+        let linear = Poly::var("a")
+            .mul(&Poly::constant(100))
+            .and_then(|p| p.add(&Poly::var("b")))
+            .and_then(|p| p.add(&Poly::constant(-7)))
+            .unwrap();
+        assert_eq!(linear.to_string(), "100*a + b - 7");
+        assert_eq!(
+            solve(&linear, 1234, ("a", 0..=99), ("b", 0..=99)),
+            Some((12, 41))
+        );
+
+        let square = Poly::var("b").mul(&Poly::var("b")).unwrap();
+        assert_eq!(
+            solve(&square, 49, ("a", 3..=4), ("b", -9..=9)),
+            Some((3, -7))
+        );
+
+        let negated = Poly::var("b").mul(&Poly::constant(-1)).unwrap();
+        let everything = ("b", i64::MIN..=i64::MAX);
+        assert_eq!(solve(&negated, i64::MIN, ("a", 0..=0), everything), None);
+    }
+
+    #[test]
+    fn huge_addresses_are_rejected() {
+        let mut computer = SymbolicComputer::new(&parse_program("1101,1,1,1099511627776,99"));
+        assert_eq!(
+            computer.run(),
+            Err(SymbolicError::Intcode(IntcodeError::AddressTooLarge {
+                ip: 0,
+                opcode: 1101,
+                address: 1 << 40
+            }))
+        );
+    }
+
+    #[test]
+    fn symbolic_writes_are_rejected() {
+        let mut computer = SymbolicComputer::new(&parse_program("1101,1,1,0,99"));
+        computer.symbolize(3, "x");
+        assert_eq!(
+            computer.run(),
+            Err(SymbolicError::NotConcrete {
+                ip: 0,
+                opcode: 1101
+            })
+        );
+    }
+}
@@ -0,0 +1,178 @@
+// Execution traces, one line per executed instruction:
+//
+//     ip <TAB> raw words <TAB> disassembly <TAB> operand values <TAB> writes
+//     2	1001,9,1,9	ADD [9], #1, [9]	3,1,3	9=4
+//
+// Operand values are what each parameter resolved to before the instruction
+// ran. The disassembly column is informational and ignored when reading. An
+// instruction that faults didn't run, and leaves no line.
+
+use super::word::Word;
+use super::{get_instruction, Instruction};
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEntry<W: Word = i64> {
+    pub ip: usize,
+    pub instruction: Instruction<W>,
+    pub operands: Vec<W>,
+    pub writes: Vec<(usize, W)>,
+}
+
+fn join<T: ToString>(values: impl Iterator<Item = T>) -> String {
+    values.map(|v| v.to_string()).collect::<Vec<_>>().join(",")
+}
+
+fn parse_list<W: Word>(field: &str) -> Result<Vec<W>, String> {
+    if field.is_empty() {
+        return Ok(Vec::new());
+    }
+    field
+        .split(',')
+        .map(|w| w.parse::<W>().map_err(|_| format!("bad number `{}`", w)))
+        .collect()
+}
+
+impl<W: Word> fmt::Display for TraceEntry<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let writes = self
+            .writes
+            .iter()
+            .map(|(addr, val)| format!("{}={}", addr, val));
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}",
+            self.ip,
+            join(self.instruction.encode().iter()),
+            self.instruction,
+            join(self.operands.iter()),
+            join(writes)
+        )
+    }
+}
+
+impl<W: Word> TraceEntry<W> {
+    pub fn parse(line: &str) -> Result<TraceEntry<W>, String> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 5 {
+            return Err(format!("expected 5 fields, found {}", fields.len()));
+        }
+        let ip = fields[0]
+            .parse::<usize>()
+            .map_err(|_| format!("bad ip `{}`", fields[0]))?;
+        let instruction = get_instruction(&parse_list(fields[1])?, 0).map_err(|e| e.to_string())?;
+        let operands = parse_list(fields[3])?;
+        let mut writes = Vec::new();
+        for write in fields[4].split(',').filter(|w| !w.is_empty()) {
+            let mut parts = write.splitn(2, '=');
+            let addr = parts.next().and_then(|a| a.parse::<usize>().ok());
+            let val = parts.next().and_then(|v| v.parse::<W>().ok());
+            match (addr, val) {
+                (Some(addr), Some(val)) => writes.push((addr, val)),
+                _ => return Err(format!("bad write `{}`", write)),
+            }
+        }
+        Ok(TraceEntry {
+            ip,
+            instruction,
+            operands,
+            writes,
+        })
+    }
+}
+
+pub fn write_trace<W: Word>(trace: &[TraceEntry<W>], mut out: impl Write) -> io::Result<()> {
+    for entry in trace {
+        writeln!(out, "{}", entry)?;
+    }
+    Ok(())
+}
+
+pub fn read_trace<W: Word>(input: impl BufRead) -> io::Result<Vec<TraceEntry<W>>> {
+    let mut trace = Vec::new();
+    for (n, line) in input.lines().enumerate() {
+        let entry = TraceEntry::parse(&line?).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", n + 1, e))
+        })?;
+        trace.push(entry);
+    }
+    Ok(trace)
+}
+
+// The first step at which two traces disagree. `None` on either side means
+// that trace had already ended.
+pub struct Divergence {
+    pub step: usize,
+    pub left: Option<TraceEntry>,
+    pub right: Option<TraceEntry>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let show = |entry: &Option<TraceEntry>| match entry {
+            Some(entry) => entry.to_string(),
+            None => "<end of trace>".to_string(),
+        };
+        writeln!(f, "traces diverge at step {}", self.step)?;
+        writeln!(f, "< {}", show(&self.left))?;
+        write!(f, "> {}", show(&self.right))
+    }
+}
+
+pub fn first_divergence(left: &[TraceEntry], right: &[TraceEntry]) -> Option<Divergence> {
+    let step = (0..left.len().max(right.len())).find(|&i| left.get(i) != right.get(i))?;
+    Some(Divergence {
+        step,
+        left: left.get(step).cloned(),
+        right: right.get(step).cloned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{get_computer, parse_program};
+
+    fn record(program: &str, input: Vec<i64>) -> Vec<TraceEntry> {
+        let mut computer = get_computer(&parse_program(program), input);
+        computer.enable_trace();
+        computer.run_till_halt().unwrap();
+        computer.take_trace()
+    }
+
+    #[test]
+    fn round_trips_through_text() {
+        let trace = record("3,9,1001,9,1,9,4,9,99,0", vec![3]);
+        assert_eq!(
+            trace[1].to_string(),
+            "2\t1001,9,1,9\tADD [9], #1, [9]\t3,1,3\t9=4"
+        );
+
+        let mut text = Vec::new();
+        write_trace(&trace, &mut text).unwrap();
+        assert_eq!(read_trace(&text[..]).unwrap(), trace);
+    }
+
+    #[test]
+    fn finds_first_divergence() {
+        let program = "3,9,1001,9,1,9,4,9,99,0";
+        let left = record(program, vec![3]);
+        let right = record(program, vec![5]);
+        let divergence = first_divergence(&left, &right).unwrap();
+        assert_eq!(divergence.step, 0);
+        assert_eq!(divergence.left.unwrap().writes, vec![(9, 3)]);
+        assert!(first_divergence(&left, &left).is_none());
+    }
+
+    #[test]
+    fn faults_are_not_traced() {
+        // The second ADD writes to -1
+        let mut computer = get_computer(&parse_program("1101,1,1,9,1101,1,1,-1,99,0"), vec![]);
+        computer.enable_trace();
+        assert!(computer.run().is_err());
+        let trace = computer.take_trace();
+        assert_eq!(trace.len(), 1);
+        assert_eq!(trace[0].writes, vec![(9, 2)]);
+    }
+}
@@ -0,0 +1,317 @@
+// Watchpoints on memory accesses made by the program itself, and a read-only
+// mode for its code.
+//
+// A watched read or write doesn't stop the instruction making it. The
+// machine pauses right after it instead, with `Signal::Watchpoint` from the
+// next `tick` or `run`, and the hits can then be taken with
+// `take_watch_hits`. Taking them straight after a `tick` reports them without
+// the pause. `run_watched` hands every hit to a callback and only pauses when
+// the callback says so. Writes made by the host with `store_value_at_pos`,
+// like day 2 patching in its noun and verb, are not watched.
+//
+// With `protect_code`, a write into the program's code fails with
+// `IntcodeError::WriteToCode`. Code is everything the static control-flow
+// graph reaches when protection is turned on, plus every instruction
+// executed since. Addresses a program is meant to patch can be let through
+// with `allow_code_write`.
+
+use super::cfg;
+use super::io::{InputSource, OutputSink};
+use super::memory::Memory;
+use super::word::Word;
+use super::{
+    saturate, small, Fault, Instruction, IntCodeComputer, IntcodeError, Parameter, Signal,
+};
+use std::collections::BTreeSet;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hit<W: Word = i64> {
+    // The instruction making the access
+    pub ip: usize,
+    pub address: usize,
+    // `Read` or `Write`
+    pub access: Access,
+    // The value read, or the one written
+    pub value: W,
+}
+
+impl<W: Word> fmt::Display for Hit<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let verb = match self.access {
+            Access::Write => "wrote",
+            _ => "read",
+        };
+        write!(
+            f,
+            "{} {} [{}] = {}",
+            self.ip, verb, self.address, self.value
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Watchpoints<W: Word> {
+    reads: BTreeSet<usize>,
+    writes: BTreeSet<usize>,
+    hits: Vec<Hit<W>>,
+    // Hits are waiting to be signalled
+    pending: bool,
+}
+
+impl<W: Word> Watchpoints<W> {
+    // Whether the machine should pause before its next instruction
+    pub(super) fn pause(&mut self) -> bool {
+        std::mem::replace(&mut self.pending, false)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CodeGuard {
+    code: BTreeSet<usize>,
+    allowed: BTreeSet<usize>,
+}
+
+impl CodeGuard {
+    pub(super) fn executed(&mut self, ip: usize, size: usize) {
+        self.code.extend(ip..ip + size);
+    }
+
+    pub(super) fn check(&self, address: usize) -> Result<(), Fault> {
+        match self.code.contains(&address) && !self.allowed.contains(&address) {
+            true => Err(Fault::WriteToCode(address)),
+            false => Ok(()),
+        }
+    }
+}
+
+fn write_destination<W: Word>(inst: &Instruction<W>) -> Option<&Parameter<W>> {
+    match inst {
+        Instruction::Add((_, _, dst))
+        | Instruction::Mul((_, _, dst))
+        | Instruction::LessThan((_, _, dst))
+        | Instruction::Equals((_, _, dst))
+        | Instruction::Input(dst) => Some(dst),
+        _ => None,
+    }
+}
+
+impl<M: Memory> IntCodeComputer<M> {
+    pub fn watch(&mut self, address: usize, access: Access) -> &mut Self {
+        let watch = self.watchpoints.get_or_insert_with(Watchpoints::default);
+        if access != Access::Write {
+            watch.reads.insert(address);
+        }
+        if access != Access::Read {
+            watch.writes.insert(address);
+        }
+        self
+    }
+
+    pub fn unwatch(&mut self, address: usize) -> &mut Self {
+        if let Some(watch) = self.watchpoints.as_mut() {
+            watch.reads.remove(&address);
+            watch.writes.remove(&address);
+        }
+        self
+    }
+
+    // Taking the hits also cancels the pause they were going to cause
+    pub fn take_watch_hits(&mut self) -> Vec<Hit<M::Word>> {
+        match self.watchpoints.as_mut() {
+            Some(watch) => {
+                watch.pending = false;
+                std::mem::take(&mut watch.hits)
+            }
+            None => Vec::new(),
+        }
+    }
+
+    fn memory_address(&self, param: &Parameter<M::Word>) -> Option<usize> {
+        match param {
+            Parameter::Position(pos) => small(pos).and_then(|pos| self.address(pos)).ok(),
+            Parameter::Relative(offset) => self.relative_address(offset).ok(),
+            Parameter::Immediate(_) => None,
+        }
+    }
+
+    // The watched reads the instruction is about to make, and the address it
+    // will write if that is watched. Nothing has run yet, so the relative
+    // base and memory are as the instruction sees them.
+    pub(super) fn watched_accesses(
+        &self,
+        ip: usize,
+        inst: &Instruction<M::Word>,
+    ) -> (Vec<Hit<M::Word>>, Option<usize>) {
+        let watch = match self.watchpoints.as_ref() {
+            Some(watch) => watch,
+            None => return (Vec::new(), None),
+        };
+        let destination = write_destination(inst);
+        let mut params = inst.params();
+        if let Instruction::JumpIfTrue((cond, _)) | Instruction::JumpIfFalse((cond, _)) = inst {
+            // The target is only read when the jump is taken
+            let jumps = self
+                .unwrap_value(cond.clone())
+                .is_ok_and(|v| v.is_zero() == matches!(inst, Instruction::JumpIfFalse(_)));
+            if !jumps {
+                params.pop();
+            }
+        }
+        // The destination always comes last
+        let reads = params
+            .iter()
+            .take(params.len() - destination.is_some() as usize)
+            .filter_map(|param| self.memory_address(param))
+            .filter(|address| watch.reads.contains(address))
+            .map(|address| Hit {
+                ip,
+                address,
+                access: Access::Read,
+                value: self.memory.get(address),
+            })
+            .collect();
+        let write = destination
+            .and_then(|dst| self.memory_address(dst))
+            .filter(|address| watch.writes.contains(address));
+        (reads, write)
+    }
+
+    pub(super) fn record_hits(
+        &mut self,
+        ip: usize,
+        mut hits: Vec<Hit<M::Word>>,
+        write: Option<usize>,
+    ) {
+        if let Some(address) = write {
+            hits.push(Hit {
+                ip,
+                address,
+                access: Access::Write,
+                value: self.memory.get(address),
+            });
+        }
+        if let Some(watch) = self.watchpoints.as_mut().filter(|_| !hits.is_empty()) {
+            watch.hits.extend(hits);
+            watch.pending = true;
+        }
+    }
+
+    // Like `run_with_io`, but calls `on_hit` for every watchpoint hit and
+    // keeps going as long as it returns true
+    pub fn run_watched(
+        &mut self,
+        input: &mut impl InputSource<M::Word>,
+        output: &mut impl OutputSink<M::Word>,
+        mut on_hit: impl FnMut(&Hit<M::Word>) -> bool,
+    ) -> Result<Signal, IntcodeError> {
+        loop {
+            match self.run_with_io(input, output)? {
+                Signal::Watchpoint => {
+                    let hits = self.take_watch_hits();
+                    let mut keep_going = true;
+                    for hit in &hits {
+                        keep_going &= on_hit(hit);
+                    }
+                    if !keep_going {
+                        return Ok(Signal::Watchpoint);
+                    }
+                }
+                signal => return Ok(signal),
+            }
+        }
+    }
+
+    // Makes the program's code read-only from here on
+    pub fn protect_code(&mut self) -> &mut Self {
+        let program: Vec<i64> = self.memory.to_vec().iter().map(saturate).collect();
+        let graph = cfg::build(&program);
+        let guard = self.code_guard.get_or_insert_with(CodeGuard::default);
+        for block in graph.blocks.values() {
+            guard.code.extend(block.start..block.end);
+        }
+        self
+    }
+
+    pub fn allow_code_write(&mut self, address: usize) -> &mut Self {
+        if let Some(guard) = self.code_guard.as_mut() {
+            guard.allowed.insert(address);
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{get_computer, parse_program};
+    use std::collections::VecDeque;
+
+    // Adds its two inputs into [11] and outputs it
+    const ADDER: &str = "3,11,3,12,1,11,12,11,4,11,99,0,0";
+
+    #[test]
+    fn pauses_after_watched_accesses() {
+        let mut computer = get_computer(&parse_program(ADDER), vec![3, 4]);
+        computer.watch(11, Access::Write).watch(12, Access::Read);
+        let mut outputs = Vec::new();
+        let mut hits = Vec::new();
+        let mut input = VecDeque::new();
+        while computer.run_with_io(&mut input, &mut outputs) == Ok(Signal::Watchpoint) {
+            hits.extend(computer.take_watch_hits().iter().map(|h| h.to_string()));
+        }
+        assert_eq!(
+            hits,
+            vec!["0 wrote [11] = 3", "4 read [12] = 4", "4 wrote [11] = 7"]
+        );
+        assert_eq!(outputs, vec![7]);
+    }
+
+    #[test]
+    fn callback_decides_whether_to_pause() {
+        let mut computer = get_computer(&parse_program(ADDER), vec![3, 4]);
+        computer.watch(11, Access::ReadWrite);
+        let mut seen = 0;
+        let signal = computer.run_watched(&mut VecDeque::new(), &mut Vec::new(), |hit| {
+            seen += 1;
+            hit.access != Access::Read
+        });
+        // Paused after the ADD reads [11]
+        assert_eq!(signal, Ok(Signal::Watchpoint));
+        assert_eq!((seen, computer.ip()), (3, 8));
+
+        computer.unwatch(11);
+        assert_eq!(computer.run_till_halt(), Ok(vec![7]));
+    }
+
+    #[test]
+    fn read_only_code() {
+        // The day 2 sample writes over its own operands
+        let program = parse_program("1,9,10,3,2,3,11,0,99,30,40,50");
+        let mut computer = get_computer(&program, vec![]);
+        computer.protect_code();
+        assert_eq!(
+            computer.run_till_halt(),
+            Err(IntcodeError::WriteToCode {
+                ip: 0,
+                opcode: 1,
+                address: 3
+            })
+        );
+
+        let mut computer = get_computer(&program, vec![]);
+        computer
+            .protect_code()
+            .allow_code_write(3)
+            .allow_code_write(0);
+        assert_eq!(computer.run_till_halt(), Ok(vec![]));
+        assert_eq!(computer.get_value_at_pos(0), 3500);
+    }
+}
@@ -0,0 +1,90 @@
+// Word types the machine can run with. Arithmetic is checked, so a program
+// that outgrows its word stops with `IntcodeError::Overflow` instead of
+// wrapping. `i64` is the default, `i128` buys some headroom and `BigInt`
+// never overflows.
+
+use num_bigint::BigInt;
+use num_traits::{ToPrimitive, Zero};
+use std::convert::TryFrom;
+use std::fmt::{Debug, Display};
+use std::str::FromStr;
+
+pub trait Word: Clone + Debug + Default + Display + FromStr + Ord {
+    fn from_i64(n: i64) -> Self;
+
+    // `None` if the value does not fit, e.g. an address or opcode that is
+    // far too large
+    fn to_i64(&self) -> Option<i64>;
+
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+
+    fn is_zero(&self) -> bool;
+}
+
+impl Word for i64 {
+    fn from_i64(n: i64) -> Self {
+        n
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        Some(*self)
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        i64::checked_add(*self, *other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        i64::checked_mul(*self, *other)
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == 0
+    }
+}
+
+impl Word for i128 {
+    fn from_i64(n: i64) -> Self {
+        n.into()
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        i64::try_from(*self).ok()
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        i128::checked_add(*self, *other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        i128::checked_mul(*self, *other)
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == 0
+    }
+}
+
+impl Word for BigInt {
+    fn from_i64(n: i64) -> Self {
+        n.into()
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        ToPrimitive::to_i64(self)
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        Some(self * other)
+    }
+
+    fn is_zero(&self) -> bool {
+        Zero::is_zero(self)
+    }
+}
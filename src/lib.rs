@@ -12,5 +12,6 @@ pub mod day6;
 pub mod day7;
 pub mod day8;
 pub mod day9;
+pub mod intcode;
 
 aoc_lib! { year = 2019 }
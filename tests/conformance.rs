@@ -0,0 +1,333 @@
+// Conformance suite. Every fixture in tests/conformance is run on each memory
+// backend, word type and execution mode of the VM. A fixture lists programs,
+// each followed by the checks to run against a fresh machine:
+//
+//     program <words>                 or  program-file <path>
+//     run <inputs> => <outputs>       ending in `, error <message>` for a
+//                                     fault or `, blocked` for a program left
+//                                     waiting on input
+//     memory <address> <words>        memory left behind by the last run
+//     amplifiers <phases> => <signal>
+//     feedback <phases> => <signal>
+//
+// Lists are comma separated, `-` for an empty one. Blank lines and lines
+// starting with `#` are ignored.
+
+use aoc::intcode::compile::{compile, CompiledMachine};
+use aoc::intcode::memory::{CowMemory, DenseMemory, Memory, SparseMemory};
+use aoc::intcode::word::Word;
+use aoc::intcode::{parse_program, IntCodeComputer, IntcodeError, Signal};
+use num_bigint::BigInt;
+use std::collections::VecDeque;
+use std::fs;
+
+const FIXTURES: &str = "tests/conformance";
+
+const BACKENDS: [&str; 10] = [
+    "dense",
+    "sparse",
+    "copy on write",
+    "i128",
+    "BigInt",
+    "decode cache",
+    "compiled",
+    "trace",
+    "history",
+    "profile",
+];
+
+#[derive(Debug, PartialEq)]
+enum Stop {
+    Halt,
+    Blocked,
+    Error(String),
+}
+
+enum Check {
+    Run {
+        input: Vec<i64>,
+        outputs: Vec<i64>,
+        stop: Stop,
+    },
+    Memory {
+        start: usize,
+        words: Vec<i64>,
+    },
+    Amplifiers {
+        phases: Vec<i64>,
+        signal: i64,
+        feedback: bool,
+    },
+}
+
+struct Program {
+    words: Vec<i64>,
+    checks: Vec<(usize, Check)>,
+}
+
+fn parse_list(s: &str) -> Result<Vec<i64>, String> {
+    match s.trim() {
+        "-" => Ok(Vec::new()),
+        s => s
+            .split(',')
+            .map(|w| w.trim().parse().map_err(|_| format!("bad number `{}`", w)))
+            .collect(),
+    }
+}
+
+fn parse_check(keyword: &str, rest: &str) -> Result<Check, String> {
+    if keyword == "memory" {
+        let (start, words) = rest
+            .split_once(' ')
+            .ok_or("expected an address and words")?;
+        return Ok(Check::Memory {
+            start: start
+                .parse()
+                .map_err(|_| format!("bad address `{}`", start))?,
+            words: parse_list(words)?,
+        });
+    }
+    let (left, right) = rest.split_once("=>").ok_or("expected `=>`")?;
+    match keyword {
+        "run" => {
+            let mut outputs = Vec::new();
+            let mut stop = Stop::Halt;
+            for item in right.split(',').map(str::trim) {
+                match item {
+                    "-" => {}
+                    "blocked" => stop = Stop::Blocked,
+                    _ if item.starts_with("error ") => stop = Stop::Error(item[6..].to_string()),
+                    _ => outputs.push(item.parse().map_err(|_| format!("bad output `{}`", item))?),
+                }
+            }
+            Ok(Check::Run {
+                input: parse_list(left)?,
+                outputs,
+                stop,
+            })
+        }
+        "amplifiers" | "feedback" => Ok(Check::Amplifiers {
+            phases: parse_list(left)?,
+            signal: right.trim().parse().map_err(|_| "bad signal")?,
+            feedback: keyword == "feedback",
+        }),
+        _ => Err(format!("unknown check `{}`", keyword)),
+    }
+}
+
+fn parse_fixture(path: &str) -> Vec<Program> {
+    let source = fs::read_to_string(path).unwrap_or_else(|e| panic!("{}: {}", path, e));
+    let mut programs: Vec<Program> = Vec::new();
+    for (n, line) in source.lines().enumerate().map(|(n, l)| (n + 1, l.trim())) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (keyword, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let location = format!("{}:{}", path, n);
+        let words = match keyword {
+            "program" => Some(parse_program(rest)),
+            "program-file" => Some(parse_program(
+                &fs::read_to_string(rest).unwrap_or_else(|e| panic!("{}: {}", location, e)),
+            )),
+            _ => None,
+        };
+        match (words, programs.last_mut()) {
+            (Some(words), _) => programs.push(Program {
+                words,
+                checks: Vec::new(),
+            }),
+            (None, Some(program)) => {
+                let check =
+                    parse_check(keyword, rest).unwrap_or_else(|e| panic!("{}: {}", location, e));
+                program.checks.push((n, check));
+            }
+            (None, None) => panic!("{}: check before any program", location),
+        }
+    }
+    programs
+}
+
+trait Machine {
+    fn feed(&mut self, value: i64);
+
+    // Runs until the program halts or blocks on input
+    fn resume(&mut self, outputs: &mut Vec<i64>) -> Result<Signal, IntcodeError>;
+
+    fn read(&self, addr: usize) -> i64;
+}
+
+impl<M: Memory> Machine for IntCodeComputer<M> {
+    fn feed(&mut self, value: i64) {
+        self.feed_input(M::Word::from_i64(value));
+    }
+
+    fn resume(&mut self, outputs: &mut Vec<i64>) -> Result<Signal, IntcodeError> {
+        let mut words = Vec::new();
+        let result = self.run_with_io(&mut VecDeque::new(), &mut words);
+        outputs.extend(words.iter().map(|w| w.to_i64().unwrap()));
+        result
+    }
+
+    fn read(&self, addr: usize) -> i64 {
+        self.get_value_at_pos(addr).to_i64().unwrap()
+    }
+}
+
+impl Machine for CompiledMachine<'_> {
+    fn feed(&mut self, value: i64) {
+        self.feed_input(value);
+    }
+
+    fn resume(&mut self, outputs: &mut Vec<i64>) -> Result<Signal, IntcodeError> {
+        self.run_with_io(&mut VecDeque::new(), outputs)
+    }
+
+    fn read(&self, addr: usize) -> i64 {
+        self.get_value_at_pos(addr)
+    }
+}
+
+type NewMachine<'a> = dyn Fn() -> Box<dyn Machine + 'a> + 'a;
+
+fn run_check<'a>(
+    check: &Check,
+    new_machine: &NewMachine<'a>,
+    last: &mut Option<Box<dyn Machine + 'a>>,
+) -> Result<(), String> {
+    match check {
+        Check::Run {
+            input,
+            outputs,
+            stop,
+        } => {
+            let mut machine = new_machine();
+            input.iter().for_each(|&i| machine.feed(i));
+            let mut actual = Vec::new();
+            let actual_stop = match machine.resume(&mut actual) {
+                Ok(Signal::Halt) => Stop::Halt,
+                Ok(_) => Stop::Blocked,
+                Err(e) => Stop::Error(e.to_string()),
+            };
+            *last = Some(machine);
+            match (&actual, &actual_stop) == (outputs, stop) {
+                true => Ok(()),
+                false => Err(format!(
+                    "expected {:?} then {:?}, got {:?} then {:?}",
+                    outputs, stop, actual, actual_stop
+                )),
+            }
+        }
+        Check::Memory { start, words } => {
+            let machine = last.as_ref().ok_or("no run to check the memory of")?;
+            let actual: Vec<i64> = (*start..start + words.len())
+                .map(|a| machine.read(a))
+                .collect();
+            match actual == *words {
+                true => Ok(()),
+                false => Err(format!("expected memory {:?}, got {:?}", words, actual)),
+            }
+        }
+        Check::Amplifiers {
+            phases,
+            signal,
+            feedback,
+        } => {
+            let mut amplifiers: Vec<_> = phases
+                .iter()
+                .map(|&phase| {
+                    let mut machine = new_machine();
+                    machine.feed(phase);
+                    machine
+                })
+                .collect();
+            let mut actual = 0;
+            loop {
+                let mut halted = false;
+                for amplifier in amplifiers.iter_mut() {
+                    amplifier.feed(actual);
+                    let mut outputs = Vec::new();
+                    halted =
+                        amplifier.resume(&mut outputs).map_err(|e| e.to_string())? == Signal::Halt;
+                    actual = *outputs.last().ok_or("an amplifier produced no signal")?;
+                }
+                if halted || !feedback {
+                    break;
+                }
+            }
+            match actual == *signal {
+                true => Ok(()),
+                false => Err(format!("expected signal {}, got {}", signal, actual)),
+            }
+        }
+    }
+}
+
+fn run_checks(program: &Program, new_machine: &NewMachine) -> Vec<(usize, String)> {
+    let mut last = None;
+    program
+        .checks
+        .iter()
+        .filter_map(|(line, check)| {
+            run_check(check, new_machine, &mut last)
+                .err()
+                .map(|e| (*line, e))
+        })
+        .collect()
+}
+
+fn computer<M: Memory>(words: &[i64]) -> IntCodeComputer<M> {
+    let mut computer = IntCodeComputer::new();
+    computer.load_memory(words.iter().map(|&w| M::Word::from_i64(w)).collect());
+    computer
+}
+
+fn on_backend(backend: &str, program: &Program) -> Vec<(usize, String)> {
+    let words = &program.words;
+    match backend {
+        "dense" => run_checks(program, &|| Box::new(computer::<DenseMemory>(words))),
+        "sparse" => run_checks(program, &|| Box::new(computer::<SparseMemory>(words))),
+        "copy on write" => run_checks(program, &|| Box::new(computer::<CowMemory>(words))),
+        "i128" => run_checks(program, &|| Box::new(computer::<DenseMemory<i128>>(words))),
+        "BigInt" => run_checks(program, &|| {
+            Box::new(computer::<DenseMemory<BigInt>>(words))
+        }),
+        "compiled" => {
+            let compiled = compile(words);
+            run_checks(program, &|| Box::new(compiled.machine(vec![])))
+        }
+        mode => run_checks(program, &|| {
+            let mut computer = computer::<DenseMemory>(words);
+            match mode {
+                "decode cache" => computer.enable_decode_cache(),
+                "trace" => computer.enable_trace(),
+                "history" => computer.enable_history(64),
+                "profile" => computer.enable_profile(),
+                _ => unreachable!(),
+            };
+            Box::new(computer)
+        }),
+    }
+}
+
+#[test]
+fn conformance() {
+    let mut paths: Vec<String> = fs::read_dir(FIXTURES)
+        .unwrap()
+        .map(|entry| entry.unwrap().path().to_string_lossy().into_owned())
+        .filter(|path| path.ends_with(".txt"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no fixtures in {}", FIXTURES);
+
+    let mut failures = Vec::new();
+    for path in &paths {
+        for program in parse_fixture(path) {
+            for backend in &BACKENDS {
+                for (line, message) in on_backend(backend, &program) {
+                    failures.push(format!("{}:{} ({}): {}", path, line, backend, message));
+                }
+            }
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}